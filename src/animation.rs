@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+
+/// Frame timing for an animated image, independent of the textures being shown
+#[derive(Debug)]
+pub struct Playback {
+    delays: Vec<Duration>,
+    current: usize,
+    paused: bool,
+    next_at: Instant,
+}
+
+impl Playback {
+    pub fn new(delays: Vec<Duration>, now: Instant) -> Self {
+        let first = delays.first().copied().unwrap_or_default();
+        Self {
+            delays,
+            current: 0,
+            paused: false,
+            next_at: now + first,
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.delays.len()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// When the event loop should next wake to advance a frame, if playing
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.paused || self.delays.len() < 2 {
+            None
+        } else {
+            Some(self.next_at)
+        }
+    }
+
+    /// Moves on to whichever frame should be showing at `now`, looping at the end
+    ///
+    /// Returns whether the frame changed.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        if self.next_deadline().is_none() {
+            return false;
+        }
+        let before = self.current;
+        while self.next_at <= now {
+            self.current = (self.current + 1) % self.delays.len();
+            self.next_at += self.delays[self.current];
+            // a run of zero length frames would otherwise never catch up
+            if self.delays[self.current].is_zero() {
+                self.next_at = now;
+                break;
+            }
+        }
+        before != self.current
+    }
+
+    pub fn toggle_pause(&mut self, now: Instant) {
+        self.paused = !self.paused;
+        if !self.paused {
+            self.next_at = now + self.delays[self.current];
+        }
+    }
+
+    /// Pauses and shows the next frame
+    pub fn step_forward(&mut self) {
+        self.paused = true;
+        self.current = (self.current + 1) % self.delays.len();
+    }
+
+    /// Pauses and shows the previous frame
+    pub fn step_back(&mut self) {
+        self.paused = true;
+        self.current = (self.current + self.delays.len() - 1) % self.delays.len();
+    }
+}
+
+#[cfg(test)]
+mod animation_tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_advance_follows_delays() {
+        let start = Instant::now();
+        let mut playback = Playback::new(vec![ms(100), ms(200), ms(50)], start);
+
+        assert!(!playback.advance(start + ms(99)));
+        assert_eq!(playback.current(), 0);
+        assert!(playback.advance(start + ms(100)));
+        assert_eq!(playback.current(), 1);
+        assert!(!playback.advance(start + ms(299)));
+        assert!(playback.advance(start + ms(300)));
+        assert_eq!(playback.current(), 2);
+        assert_eq!(playback.next_deadline(), Some(start + ms(350)));
+    }
+
+    #[test]
+    fn test_advance_loops() {
+        let start = Instant::now();
+        let mut playback = Playback::new(vec![ms(100), ms(100)], start);
+
+        assert!(playback.advance(start + ms(100)));
+        assert!(playback.advance(start + ms(200)));
+        assert_eq!(playback.current(), 0);
+        // a late wake up skips over frames that should already have been shown
+        assert!(playback.advance(start + ms(350)));
+        assert_eq!(playback.current(), 1);
+    }
+
+    #[test]
+    fn test_pause_and_step() {
+        let start = Instant::now();
+        let mut playback = Playback::new(vec![ms(100), ms(100), ms(100)], start);
+
+        playback.toggle_pause(start);
+        assert!(playback.is_paused());
+        assert_eq!(playback.next_deadline(), None);
+        assert!(!playback.advance(start + ms(500)));
+
+        playback.step_back();
+        assert_eq!(playback.current(), 2);
+        playback.step_forward();
+        playback.step_forward();
+        assert_eq!(playback.current(), 1);
+
+        playback.toggle_pause(start + ms(1000));
+        assert_eq!(playback.next_deadline(), Some(start + ms(1100)));
+    }
+
+    #[test]
+    fn test_single_frame_never_wakes() {
+        let start = Instant::now();
+        let playback = Playback::new(vec![ms(100)], start);
+        assert_eq!(playback.next_deadline(), None);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use log::{info, warn};
+
+use crate::format::Format;
+
+/// Where the previous contents of `path` are kept when it is overwritten with a backup
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// The temporary file `path` is written to, in the same directory so it can be renamed over it
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", process::id()));
+    path.with_file_name(name)
+}
+
+/// Whether `path` is a backup or temporary file left by [`write`] rather than an image of its own
+///
+/// Only names [`backup_path`] and [`temp_path`] give an image are matched, so other `.bak` and
+/// `.tmp` files are left alone.
+pub fn is_leftover(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let is_image = |name: &str| Format::from_extension(Path::new(name)).is_some();
+    if let Some(original) = name.strip_suffix(".bak") {
+        return is_image(original);
+    }
+    let temp = name
+        .strip_prefix('.')
+        .and_then(|name| name.strip_suffix(".tmp"))
+        .and_then(|name| name.rsplit_once('.'));
+    match temp {
+        Some((original, pid)) => {
+            !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit()) && is_image(original)
+        }
+        None => false,
+    }
+}
+
+/// Replaces the contents of `path` with `data` without ever leaving it partly written
+///
+/// The data goes to a temporary file next to `path` which is then renamed over it, so a crash
+/// or full disk leaves either the old file or the new one. With `backup`, a file being replaced
+/// is first copied to [`backup_path`].
+pub fn write(path: &Path, data: &[u8], backup: bool) -> io::Result<()> {
+    let temp = temp_path(path);
+    let result = write_temp(&temp, path, data).and_then(|()| {
+        if backup && path.is_file() {
+            let backup = backup_path(path);
+            fs::copy(path, &backup)?;
+            info!("kept the previous {:?} as {:?}", path, backup);
+        }
+        fs::rename(&temp, path)
+    });
+    if result.is_err() {
+        if let Err(err) = fs::remove_file(&temp) {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!("could not remove {:?}: {}", temp, err);
+            }
+        }
+    }
+    result
+}
+
+fn write_temp(temp: &Path, path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    file.write_all(data)?;
+    // the replacement keeps the permissions of the file it replaces
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod atomic_write_tests {
+    use super::*;
+    use crate::{format, test_dir::TestDir};
+
+    #[test]
+    fn test_write_replaces() {
+        let dir = TestDir::new("atomic_write_replace");
+        let path = dir.join("image.png");
+        write(&path, b"first", false).unwrap();
+        write(&path, b"second", false).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert!(!backup_path(&path).exists());
+        // nothing is left behind but the file itself
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_backup() {
+        let dir = TestDir::new("atomic_write_backup");
+        let path = dir.join("image.png");
+        write(&path, b"first", true).unwrap();
+        assert!(!backup_path(&path).exists());
+        write(&path, b"second", true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(backup_path(&path), dir.join("image.png.bak"));
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), b"first");
+        assert!(is_leftover(&backup_path(&path)));
+        assert!(is_leftover(&temp_path(&path)));
+        assert!(!is_leftover(&path));
+    }
+
+    #[test]
+    fn test_leftover_names() {
+        for name in [
+            "x.png.bak",
+            ".x.png.123.tmp",
+            ".IMG_1.JPG.4.tmp",
+            "..hidden.qoi.5.tmp",
+        ] {
+            assert!(is_leftover(Path::new(name)), "{}", name);
+        }
+        for name in [
+            "x.png",
+            "notes.bak",
+            ".x.png.bak.swp",
+            ".cache.tmp",
+            ".x.png.tmp",
+            ".x.png.12a.tmp",
+            "x.png.123.tmp",
+            ".notes.txt.123.tmp",
+        ] {
+            assert!(!is_leftover(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_leftovers_are_not_browsed() {
+        let dir = TestDir::new("atomic_write_leftovers");
+        for name in ["x.png", "x.png.bak", ".x.png.123.tmp", "y.bak"] {
+            fs::copy("./test_images/dice.png", dir.join(name)).unwrap();
+        }
+        let mut browsable: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| format::is_browsable(path))
+            .collect();
+        browsable.sort();
+        assert_eq!(browsable, vec![dir.join("x.png"), dir.join("y.bak")]);
+    }
+
+    #[test]
+    fn test_failed_write_keeps_original() {
+        let dir = TestDir::new("atomic_write_failed");
+        // a directory cannot be replaced by a file
+        let path = dir.join("image.png");
+        fs::create_dir(&path).unwrap();
+        assert!(write(&path, b"data", false).is_err());
+        assert!(path.is_dir());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert!(write(&dir.join("missing").join("image.png"), b"data", false).is_err());
+    }
+}
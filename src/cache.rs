@@ -0,0 +1,255 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use glium::glutin::event_loop::EventLoopProxy;
+use log::{debug, trace, warn};
+
+use crate::{
+    image_loading::{self, Decoded},
+    UserEvent,
+};
+
+/// Decoded images kept in memory for instant navigation
+pub const CACHE_BUDGET: usize = 512 * 1024 * 1024;
+/// How many images either side of the current one to decode ahead of time
+pub const PRELOAD_DISTANCE: usize = 2;
+
+/// Identifies the version of a file an entry was decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<FileStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+struct Entry {
+    image: Arc<Decoded>,
+    stamp: FileStamp,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// A least recently used cache of decoded images, bounded by their size in memory
+pub struct ImageCache {
+    entries: HashMap<PathBuf, Entry>,
+    budget: usize,
+    used: usize,
+    clock: u64,
+}
+
+impl ImageCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget,
+            used: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the cached image, dropping it instead if the file changed since it was decoded
+    pub fn get(&mut self, path: &Path) -> Option<Arc<Decoded>> {
+        let stamp = FileStamp::of(path);
+        self.clock += 1;
+        match self.entries.get_mut(path) {
+            Some(entry) if Some(entry.stamp) == stamp => {
+                entry.last_used = self.clock;
+                Some(entry.image.clone())
+            }
+            Some(_) => {
+                debug!("cache: {:?} changed on disk", path);
+                self.remove(path);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Caches an image decoded from the version of the file given by `stamp`, taken before it was
+    /// read so that a file still being written is not mistaken for the finished one
+    ///
+    /// Without a stamp the file has gone, and nothing is cached.
+    fn insert(&mut self, path: PathBuf, image: Decoded, stamp: Option<FileStamp>) {
+        let stamp = match stamp {
+            Some(stamp) => stamp,
+            None => return,
+        };
+        let bytes = image.byte_size();
+        if bytes > self.budget {
+            trace!("cache: {:?} is larger than the whole cache", path);
+            return;
+        }
+        self.remove(&path);
+        self.clock += 1;
+        self.used += bytes;
+        self.entries.insert(
+            path,
+            Entry {
+                image: Arc::new(image),
+                stamp,
+                bytes,
+                last_used: self.clock,
+            },
+        );
+        self.evict();
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.used -= entry.bytes;
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(path) => {
+                    trace!("cache: evicting {:?}", path);
+                    self.remove(&path);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Decodes images on the rayon thread pool into a shared [`ImageCache`]
+///
+/// The event loop is woken with [`UserEvent::Preloaded`] as each image finishes.
+pub struct Preloader {
+    cache: Arc<Mutex<ImageCache>>,
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+impl Preloader {
+    pub fn new(budget: usize, proxy: EventLoopProxy<UserEvent>) -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(ImageCache::new(budget))),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+            proxy,
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Arc<Decoded>> {
+        self.cache.lock().unwrap().get(path)
+    }
+
+    /// Queues `path` to be decoded unless it is already cached or being decoded
+    pub fn request(&self, path: &Path) {
+        if self.cache.lock().unwrap().get(path).is_some()
+            || !self.in_flight.lock().unwrap().insert(path.to_path_buf())
+        {
+            return;
+        }
+
+        let path = path.to_path_buf();
+        let cache = self.cache.clone();
+        let in_flight = self.in_flight.clone();
+        let proxy = self.proxy.clone();
+        rayon::spawn(move || {
+            let stamp = FileStamp::of(&path);
+            match image_loading::decode(&path) {
+                Ok(image) => {
+                    let mut cache = cache.lock().unwrap();
+                    cache.insert(path.clone(), image, stamp);
+                    debug!("preloaded {:?}, cache using {} bytes", path, cache.used());
+                }
+                Err(err) => warn!("preload: {}", err),
+            }
+            in_flight.lock().unwrap().remove(&path);
+            // the event loop is gone when the window is closing
+            let _ = proxy.send_event(UserEvent::Preloaded(path));
+        });
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use image::DynamicImage;
+
+    fn still(bytes: usize) -> Decoded {
+        Decoded::Still(DynamicImage::new_rgba8(bytes as u32 / 4, 1))
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = ImageCache::new(100);
+        let (a, b, c) = (
+            PathBuf::from("./test_images/0.jpg"),
+            PathBuf::from("./test_images/dice.png"),
+            PathBuf::from("./test_images/dice.qoi"),
+        );
+        cache.insert(a.clone(), still(40), FileStamp::of(&a));
+        cache.insert(b.clone(), still(40), FileStamp::of(&b));
+        assert!(cache.get(&a).is_some());
+
+        cache.insert(c.clone(), still(40), FileStamp::of(&c));
+        assert_eq!(cache.used(), 80);
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&c).is_some());
+    }
+
+    #[test]
+    fn test_rejects_oversized_and_missing() {
+        let mut cache = ImageCache::new(100);
+        cache.insert(
+            PathBuf::from("./test_images/0.jpg"),
+            still(400),
+            FileStamp::of(Path::new("./test_images/0.jpg")),
+        );
+        cache.insert(
+            PathBuf::from("./test_images/missing.png"),
+            still(4),
+            FileStamp::of(Path::new("./test_images/missing.png")),
+        );
+        assert_eq!(cache.used(), 0);
+    }
+
+    #[test]
+    fn test_invalidated_when_file_changes() {
+        let dir = TestDir::new("cache");
+        let path = dir.join("image.png");
+        fs::copy("./test_images/dice.png", &path).unwrap();
+
+        let mut cache = ImageCache::new(100);
+        cache.insert(path.clone(), still(8), FileStamp::of(&path));
+        assert!(cache.get(&path).is_some());
+
+        fs::copy("./test_images/qoi_logo.png", &path).unwrap();
+        assert!(cache.get(&path).is_none());
+        assert_eq!(cache.used(), 0);
+
+        // decoded from a file that grew before the decode finished
+        let stamp = FileStamp::of(&path).unwrap();
+        fs::copy("./test_images/dice.png", &path).unwrap();
+        cache.insert(path.clone(), still(8), Some(stamp));
+        assert!(cache.get(&path).is_none());
+    }
+}
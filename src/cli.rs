@@ -0,0 +1,454 @@
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use log::LevelFilter;
+
+use crate::{config::Config, format::Format, image_saving, metadata::Strip, sort::SortMode};
+
+pub const USAGE: &str = "\
+Usage: femtophotos [OPTIONS] [PATH...]
+       femtophotos convert [CONVERT OPTIONS] --to <FORMAT> --out <DIR> <PATH...>
+
+Opens the given images or directories. With several paths, or a list, only those are
+browsed, otherwise the directory of the image is. A PATH of - reads a list from stdin, and
+.m3u and .m3u8 files are read as lists.
+
+Options:
+  -f, --fullscreen         start fullscreen
+      --slideshow <SECS>   start a slideshow, moving on every SECS seconds
+      --sort <ORDER>       browse in name, mtime, size or exif-date order (default name)
+      --reverse            browse in the opposite order
+      --shuffle <SEED>     browse in a random order, the same each time for the same SEED
+      --follow             open each image added to the directory, for tethered shooting
+  -r, --recursive          include images in subdirectories
+      --list <FILE>        browse the images named in FILE, one per line, or stdin for -
+      --start-at <FILE>    the image to open first
+      --strip <WHAT>       leave gps or all metadata out of saved images (default none)
+      --set <KEY=VALUE>    override a setting of femtophotos.conf, such as jpeg.quality=80
+      --log-level <LEVEL>  off, error, warn, info, debug or trace (default trace)
+  -h, --help               print this message
+  -V, --version            print the version
+
+Convert options:
+      --to <FORMAT>        jpg, png, qoi, webp, tif, bmp or gif
+      --out <DIR>          where to write the converted images, created if missing
+      --no-rotate          keep the stored orientation instead of applying EXIF rotation
+      --strip <WHAT>       as above
+      --set <KEY=VALUE>    as above
+      --log-level <LEVEL>  as above";
+
+/// What the viewer was asked to do
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    View(Options),
+    Convert(ConvertOptions),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub paths: Vec<PathBuf>,
+    /// Files naming images to browse, `-` being stdin
+    pub lists: Vec<PathBuf>,
+    pub fullscreen: bool,
+    pub slideshow: Option<Duration>,
+    pub sort: SortMode,
+    pub reverse: bool,
+    /// The seed of a random browsing order
+    pub shuffle: Option<u64>,
+    pub follow: bool,
+    pub recursive: bool,
+    pub start_at: Option<PathBuf>,
+    pub strip: Strip,
+    /// Settings overriding the config file, as `key`, `value`
+    pub settings: Vec<(String, String)>,
+    pub log_level: LevelFilter,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            lists: Vec::new(),
+            fullscreen: false,
+            slideshow: None,
+            sort: SortMode::default(),
+            reverse: false,
+            shuffle: None,
+            follow: false,
+            recursive: false,
+            start_at: None,
+            strip: Strip::default(),
+            settings: Vec::new(),
+            log_level: LevelFilter::Trace,
+        }
+    }
+}
+
+/// Options for converting images without opening a window
+#[derive(Debug, PartialEq)]
+pub struct ConvertOptions {
+    /// Images, or directories whose images are all converted
+    pub inputs: Vec<PathBuf>,
+    pub to: Format,
+    pub out: PathBuf,
+    pub auto_rotate: bool,
+    pub strip: Strip,
+    pub settings: Vec<(String, String)>,
+    pub log_level: LevelFilter,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(&'static str),
+    MissingOption(&'static str),
+    InvalidValue { option: &'static str, value: String },
+    NoSuchPath(PathBuf),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            CliError::MissingValue(option) => write!(f, "{} needs a value", option),
+            CliError::MissingOption(option) => write!(f, "{} is required", option),
+            CliError::InvalidValue { option, value } => {
+                write!(f, "invalid value '{}' for {}", value, option)
+            }
+            CliError::NoSuchPath(path) => write!(f, "{:?} does not exist", path),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+/// Parses the command line arguments, excluding the program name
+pub fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Command, CliError> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().is_some_and(|arg| arg == "convert") {
+        args.next();
+        return parse_convert(args);
+    }
+
+    let mut options = Options::default();
+    let mut only_paths = false;
+
+    while let Some(arg) = args.next() {
+        let text = match arg.to_str() {
+            Some(text) if !only_paths && text.starts_with('-') && text != "-" => text,
+            _ => {
+                let path = PathBuf::from(arg);
+                if path == Path::new("-") || is_list(&path) {
+                    options.lists.push(list(path)?);
+                } else {
+                    options.paths.push(existing(path)?);
+                }
+                continue;
+            }
+        };
+        let (name, inline) = split_inline(text);
+        let mut value = |option: &'static str| {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError::MissingValue(option))
+        };
+
+        match name {
+            "--" => only_paths = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-f" | "--fullscreen" => options.fullscreen = true,
+            "-r" | "--recursive" => options.recursive = true,
+            "--list" => options.lists.push(list(PathBuf::from(value("--list")?))?),
+            "--slideshow" => {
+                let secs: f32 = parse_value("--slideshow", value("--slideshow")?)?;
+                if !secs.is_finite() || secs <= 0.0 {
+                    return Err(invalid("--slideshow", secs.to_string()));
+                }
+                options.slideshow = Some(Duration::from_secs_f32(secs));
+            }
+            "--sort" => options.sort = parse_value("--sort", value("--sort")?)?,
+            "--reverse" => options.reverse = true,
+            "--shuffle" => options.shuffle = Some(parse_value("--shuffle", value("--shuffle")?)?),
+            "--follow" => options.follow = true,
+            "--start-at" => {
+                let path = PathBuf::from(value("--start-at")?);
+                options.start_at = Some(existing(path)?);
+            }
+            "--strip" => options.strip = parse_value("--strip", value("--strip")?)?,
+            "--set" => options.settings.push(setting(value("--set")?)?),
+            "--log-level" => {
+                options.log_level = parse_value("--log-level", value("--log-level")?)?;
+            }
+            _ => return Err(CliError::UnknownOption(text.to_string())),
+        }
+    }
+    Ok(Command::View(options))
+}
+
+fn parse_convert<I: Iterator<Item = OsString>>(mut args: I) -> Result<Command, CliError> {
+    let mut inputs = Vec::new();
+    let mut to = None;
+    let mut out = None;
+    let mut auto_rotate = true;
+    let mut strip = Strip::default();
+    let mut settings = Vec::new();
+    let mut log_level = LevelFilter::Trace;
+    let mut only_paths = false;
+
+    while let Some(arg) = args.next() {
+        let text = match arg.to_str() {
+            Some(text) if !only_paths && text.starts_with('-') => text,
+            _ => {
+                inputs.push(existing(PathBuf::from(arg))?);
+                continue;
+            }
+        };
+        let (name, inline) = split_inline(text);
+        let mut value = |option: &'static str| {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError::MissingValue(option))
+        };
+
+        match name {
+            "--" => only_paths = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "--to" => {
+                let value = value("--to")?;
+                let ext = value.to_string_lossy();
+                let format = image_saving::save_format(Path::new(&format!("_.{}", ext)));
+                to = Some(format.ok_or_else(|| invalid("--to", ext.into_owned()))?);
+            }
+            "--out" => out = Some(PathBuf::from(value("--out")?)),
+            "--no-rotate" => auto_rotate = false,
+            "--strip" => strip = parse_value("--strip", value("--strip")?)?,
+            "--set" => settings.push(setting(value("--set")?)?),
+            "--log-level" => log_level = parse_value("--log-level", value("--log-level")?)?,
+            _ => return Err(CliError::UnknownOption(text.to_string())),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(CliError::MissingOption("an input path"));
+    }
+    Ok(Command::Convert(ConvertOptions {
+        inputs,
+        to: to.ok_or(CliError::MissingOption("--to"))?,
+        out: out.ok_or(CliError::MissingOption("--out"))?,
+        auto_rotate,
+        strip,
+        settings,
+        log_level,
+    }))
+}
+
+/// Splits `--opt=value`, as both it and `--opt value` are accepted
+fn split_inline(text: &str) -> (&str, Option<OsString>) {
+    match text.split_once('=') {
+        Some((name, value)) if name.starts_with("--") => (name, Some(OsString::from(value))),
+        _ => (text, None),
+    }
+}
+
+fn existing(path: PathBuf) -> Result<PathBuf, CliError> {
+    if path.exists() {
+        Ok(path)
+    } else {
+        Err(CliError::NoSuchPath(path))
+    }
+}
+
+fn is_list(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+}
+
+/// A list file, which must exist unless it is `-` for stdin
+fn list(path: PathBuf) -> Result<PathBuf, CliError> {
+    if path == Path::new("-") {
+        Ok(path)
+    } else {
+        existing(path)
+    }
+}
+
+fn invalid(option: &'static str, value: String) -> CliError {
+    CliError::InvalidValue { option, value }
+}
+
+/// Splits `key=value`, checking it names a setting and a value it can take
+fn setting(value: OsString) -> Result<(String, String), CliError> {
+    let text = value.to_string_lossy();
+    match text.split_once('=') {
+        Some((key, value)) if Config::default().set(key, value).is_ok() => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(invalid("--set", text.into_owned())),
+    }
+}
+
+fn parse_value<T: FromStr>(option: &'static str, value: OsString) -> Result<T, CliError> {
+    let text = value.to_string_lossy();
+    text.parse().map_err(|_| invalid(option, text.into_owned()))
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, CliError> {
+        parse(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn test_no_args() {
+        assert_eq!(parse_args(&[]), Ok(Command::View(Options::default())));
+    }
+
+    #[test]
+    fn test_all_options() {
+        let command = parse_args(&[
+            "./test_images/0.jpg",
+            "--fullscreen",
+            "--slideshow",
+            "2.5",
+            "--sort=mtime",
+            "--reverse",
+            "--shuffle=42",
+            "--follow",
+            "-r",
+            "--list",
+            "-",
+            "--start-at",
+            "./test_images/dice.png",
+            "--log-level",
+            "warn",
+            "./test_images",
+        ]);
+        assert_eq!(
+            command,
+            Ok(Command::View(Options {
+                paths: vec!["./test_images/0.jpg".into(), "./test_images".into()],
+                lists: vec!["-".into()],
+                fullscreen: true,
+                slideshow: Some(Duration::from_millis(2500)),
+                sort: SortMode::Modified,
+                reverse: true,
+                shuffle: Some(42),
+                follow: true,
+                recursive: true,
+                start_at: Some("./test_images/dice.png".into()),
+                strip: Strip::None,
+                settings: Vec::new(),
+                log_level: LevelFilter::Warn,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let command = parse_args(&["-", "./test_images/missing.m3u"]);
+        assert_eq!(
+            command,
+            Err(CliError::NoSuchPath("./test_images/missing.m3u".into()))
+        );
+        match parse_args(&["-", "--", "-"]) {
+            Ok(Command::View(options)) => {
+                assert_eq!(options.lists, ["-", "-"].map(PathBuf::from));
+                assert!(options.paths.is_empty());
+            }
+            command => panic!("{:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_help_and_version() {
+        assert_eq!(parse_args(&["-r", "--help"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_args(&["./test_images/missing.png"]),
+            Err(CliError::NoSuchPath("./test_images/missing.png".into()))
+        );
+        assert_eq!(
+            parse_args(&["--bogus"]),
+            Err(CliError::UnknownOption("--bogus".into()))
+        );
+        assert_eq!(
+            parse_args(&["--sort"]),
+            Err(CliError::MissingValue("--sort"))
+        );
+        assert_eq!(
+            parse_args(&["--sort", "colour"]),
+            Err(invalid("--sort", "colour".into()))
+        );
+        assert_eq!(
+            parse_args(&["--slideshow", "0"]),
+            Err(invalid("--slideshow", "0".into()))
+        );
+        assert_eq!(
+            parse_args(&["--set", "jpeg.quality=high"]),
+            Err(invalid("--set", "jpeg.quality=high".into()))
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(
+            parse_args(&[
+                "convert",
+                "./test_images",
+                "--to",
+                "QOI",
+                "--out=out",
+                "--no-rotate",
+                "--strip=gps",
+                "--set",
+                "jpeg.quality=80"
+            ]),
+            Ok(Command::Convert(ConvertOptions {
+                inputs: vec!["./test_images".into()],
+                to: Format::Qoi,
+                out: "out".into(),
+                auto_rotate: false,
+                strip: Strip::Gps,
+                settings: vec![("jpeg.quality".into(), "80".into())],
+                log_level: LevelFilter::Trace,
+            }))
+        );
+        assert_eq!(
+            parse_args(&["convert", "./test_images", "--to", "ico", "--out", "out"]),
+            Err(invalid("--to", "ico".into()))
+        );
+        assert_eq!(
+            parse_args(&["convert", "./test_images", "--to", "png"]),
+            Err(CliError::MissingOption("--out"))
+        );
+        assert_eq!(
+            parse_args(&["convert", "--to", "png", "--out", "out"]),
+            Err(CliError::MissingOption("an input path"))
+        );
+    }
+
+    #[test]
+    fn test_double_dash_ends_options() {
+        assert_eq!(
+            parse_args(&["--", "--help"]),
+            Err(CliError::NoSuchPath("--help".into()))
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{image_saving::EncodeOptions, slideshow::SlideshowOptions};
+
+/// The name of the settings file, kept next to the executable like the log
+pub const CONFIG_FILE: &str = "femtophotos.conf";
+
+/// Every setting with a description of its values, in the order they are written
+const SETTINGS: [(&str, &str); 12] = [
+    ("jpeg.quality", "1 to 100"),
+    ("jpeg.subsampling", "444, 422 or 420"),
+    ("jpeg.progressive", "true or false"),
+    ("png.compression", "fast, default or best"),
+    ("png.filter", "none, sub, up, avg, paeth or adaptive"),
+    ("png.depth", "keep or 8"),
+    (
+        "png.colour",
+        "none, gamma or srgb, srgb being left out for an ICC profile",
+    ),
+    ("webp.quality", "0 to 100, or lossless"),
+    (
+        "save.backup",
+        "true or false, whether an overwritten file is kept as .bak",
+    ),
+    (
+        "browse.wrap",
+        "true or false, whether browsing past the last image goes back to the first",
+    ),
+    ("slideshow.interval", "seconds each image is shown for"),
+    (
+        "slideshow.fade",
+        "seconds to cross-fade between images, 0 for none",
+    ),
+];
+
+/// Settings kept between runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Config {
+    pub encode: EncodeOptions,
+    /// Keep a copy of each file that is saved over
+    pub backup: bool,
+    pub wrap: bool,
+    pub slideshow: SlideshowOptions,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SetError {
+    UnknownKey,
+    InvalidValue,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A line that is not blank, a `#` comment or `key = value`
+    Syntax {
+        line: usize,
+    },
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+}
+
+impl ConfigError {
+    /// Short, user facing description of the failure
+    pub fn reason(&self) -> String {
+        match self {
+            ConfigError::Io { path, source } => format!("{:?}: {}", path, source),
+            ConfigError::Syntax { line } => format!("line {}: expected `key = value`", line),
+            ConfigError::UnknownKey { line, key } => {
+                format!("line {}: unknown setting '{}'", line, key)
+            }
+            ConfigError::InvalidValue { line, key, value } => {
+                format!("line {}: invalid value '{}' for {}", line, value, key)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConfigError: {}", self.reason())
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Where the config is kept, if the executable's directory can be found
+    pub fn path() -> Option<PathBuf> {
+        let exe = env::current_exe().ok()?;
+        Some(exe.parent()?.join(CONFIG_FILE))
+    }
+
+    /// Reads the config at `path`, first writing the defaults there if it does not exist
+    ///
+    /// The defaults are still used when they cannot be written, such as from a read only
+    /// install, which is only logged as it is no fault of the user's.
+    pub fn load_or_create(path: &Path) -> Result<Config, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let config = Config::default();
+                match fs::write(path, config.contents()) {
+                    Ok(()) => info!("config: wrote the defaults to {:?}", path),
+                    Err(err) => warn!(
+                        "config: could not write the defaults to {:?}: {}",
+                        path, err
+                    ),
+                }
+                Ok(config)
+            }
+            Err(source) => Err(ConfigError::Io {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Reads `key = value` lines, leaving settings that are not mentioned at their defaults
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(ConfigError::Syntax { line: n + 1 })?;
+            let (key, value) = (key.trim(), value.trim());
+            config.set(key, value).map_err(|err| match err {
+                SetError::UnknownKey => ConfigError::UnknownKey {
+                    line: n + 1,
+                    key: key.to_string(),
+                },
+                SetError::InvalidValue => ConfigError::InvalidValue {
+                    line: n + 1,
+                    key: key.to_string(),
+                    value: value.to_string(),
+                },
+            })?;
+        }
+        Ok(config)
+    }
+
+    /// Writes every setting with a comment on what it can be set to
+    pub fn contents(&self) -> String {
+        let mut text = String::from("# femtophotos settings, one `key = value` per line\n");
+        for (key, values) in SETTINGS {
+            text.push_str(&format!("\n# {}\n{} = {}\n", values, key, self.get(key)));
+        }
+        text
+    }
+
+    /// Changes one setting, by the name it has in the config file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SetError> {
+        let encode = &mut self.encode;
+        match key {
+            "jpeg.quality" => {
+                encode.jpeg_quality = match value.parse() {
+                    Ok(quality @ 1..=100) => quality,
+                    _ => return Err(SetError::InvalidValue),
+                }
+            }
+            "jpeg.subsampling" => {
+                encode.jpeg_subsampling = value.parse().map_err(|()| SetError::InvalidValue)?
+            }
+            "jpeg.progressive" => {
+                encode.jpeg_progressive = value.parse().map_err(|_| SetError::InvalidValue)?
+            }
+            "png.compression" => {
+                encode.png_compression = value.parse().map_err(|()| SetError::InvalidValue)?
+            }
+            "png.filter" => {
+                encode.png_filter = value.parse().map_err(|()| SetError::InvalidValue)?
+            }
+            "png.depth" => encode.png_depth = value.parse().map_err(|()| SetError::InvalidValue)?,
+            "png.colour" => {
+                encode.png_colour = value.parse().map_err(|()| SetError::InvalidValue)?
+            }
+            "webp.quality" => {
+                encode.webp_quality = match value {
+                    "lossless" => None,
+                    _ => match value.parse() {
+                        Ok(quality @ 0..=100) => Some(quality),
+                        _ => return Err(SetError::InvalidValue),
+                    },
+                }
+            }
+            "save.backup" => self.backup = value.parse().map_err(|_| SetError::InvalidValue)?,
+            "browse.wrap" => self.wrap = value.parse().map_err(|_| SetError::InvalidValue)?,
+            "slideshow.interval" => match seconds(value)? {
+                interval if !interval.is_zero() => self.slideshow.interval = interval,
+                _ => return Err(SetError::InvalidValue),
+            },
+            "slideshow.fade" => self.slideshow.fade = seconds(value)?,
+            _ => return Err(SetError::UnknownKey),
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> String {
+        let encode = &self.encode;
+        match key {
+            "jpeg.quality" => encode.jpeg_quality.to_string(),
+            "jpeg.subsampling" => encode.jpeg_subsampling.name().to_string(),
+            "jpeg.progressive" => encode.jpeg_progressive.to_string(),
+            "png.compression" => encode.png_compression.name().to_string(),
+            "png.filter" => encode.png_filter.name().to_string(),
+            "png.depth" => encode.png_depth.name().to_string(),
+            "png.colour" => encode.png_colour.name().to_string(),
+            "webp.quality" => match encode.webp_quality {
+                Some(quality) => quality.to_string(),
+                None => String::from("lossless"),
+            },
+            "save.backup" => self.backup.to_string(),
+            "browse.wrap" => self.wrap.to_string(),
+            "slideshow.interval" => self.slideshow.interval.as_secs_f32().to_string(),
+            "slideshow.fade" => self.slideshow.fade.as_secs_f32().to_string(),
+            _ => unreachable!("{} is not a setting", key),
+        }
+    }
+}
+
+/// A non-negative number of seconds, such as `2.5`
+fn seconds(value: &str) -> Result<Duration, SetError> {
+    match value.parse::<f32>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(Duration::from_secs_f32(secs)),
+        _ => Err(SetError::InvalidValue),
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use crate::image_saving::{PngDepth, Subsampling};
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config::default();
+        config.encode.jpeg_quality = 75;
+        config.encode.jpeg_subsampling = Subsampling::S420;
+        config.encode.jpeg_progressive = true;
+        config.encode.png_depth = PngDepth::Eight;
+        config.encode.webp_quality = None;
+        config.backup = true;
+        config.wrap = true;
+        config.slideshow.interval = Duration::from_millis(2500);
+        config.slideshow.fade = Duration::ZERO;
+        assert_eq!(Config::parse(&config.contents()).unwrap(), config);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let config = Config::parse("# only a comment\n\n  jpeg.quality=50  \n").unwrap();
+        assert_eq!(config.encode.jpeg_quality, 50);
+
+        assert!(matches!(
+            Config::parse("\njpeg.quality 50"),
+            Err(ConfigError::Syntax { line: 2 })
+        ));
+        assert!(matches!(
+            Config::parse("jpeg.colour = srgb"),
+            Err(ConfigError::UnknownKey { line: 1, .. })
+        ));
+        for bad in [
+            "jpeg.quality = 0",
+            "jpeg.quality = 101",
+            "png.depth = 16",
+            "webp.quality = lossy",
+            "slideshow.interval = 0",
+            "slideshow.fade = -1",
+        ] {
+            assert!(
+                matches!(
+                    Config::parse(bad),
+                    Err(ConfigError::InvalidValue { line: 1, .. })
+                ),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_defaults_are_written() {
+        let dir = TestDir::new("config");
+        let path = dir.join(CONFIG_FILE);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(Config::load_or_create(&path).unwrap(), Config::default());
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("jpeg.quality = 100"));
+        fs::write(&path, text.replace("= 100", "= 80")).unwrap();
+        assert_eq!(
+            Config::load_or_create(&path).unwrap().encode.jpeg_quality,
+            80
+        );
+    }
+
+    #[test]
+    fn test_defaults_used_when_unwritable() {
+        let dir = TestDir::new("config_unwritable");
+        let path = dir.join("missing").join(CONFIG_FILE);
+
+        assert_eq!(Config::load_or_create(&path).unwrap(), Config::default());
+        assert!(!path.exists());
+    }
+}
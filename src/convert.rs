@@ -0,0 +1,211 @@
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use rayon::prelude::*;
+
+use crate::{
+    cli::ConvertOptions, config::Config, format, image_loading, image_saving::save_image,
+    metadata::Metadata, rotation::Orientation, source::list_dir,
+};
+
+/// Why a single file could not be converted
+#[derive(Debug)]
+pub struct ConvertError {
+    err_str: String,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.err_str)
+    }
+}
+
+impl ConvertError {
+    fn new(err_str: impl Into<String>) -> Self {
+        Self {
+            err_str: err_str.into(),
+        }
+    }
+}
+
+/// Converts every input in parallel, printing a line per file
+///
+/// Returns whether every file was converted.
+pub fn run(options: &ConvertOptions, config: &Config) -> bool {
+    if let Err(err) = fs::create_dir_all(&options.out) {
+        eprintln!("could not create {:?}: {}", options.out, err);
+        return false;
+    }
+
+    let jobs = match jobs(options) {
+        Ok(jobs) => jobs,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+    info!("converting {} images", jobs.len());
+
+    let results: Vec<Result<&PathBuf, ConvertError>> = jobs
+        .par_iter()
+        .map(|(input, output)| {
+            let output = output
+                .as_ref()
+                .map_err(|err| ConvertError::new(err.clone()))?;
+            convert_file(input, output, options, config).map(|()| output)
+        })
+        .collect();
+
+    let mut failed = 0;
+    for ((input, _), result) in jobs.iter().zip(&results) {
+        match result {
+            Ok(output) => println!("ok    {:?} -> {:?}", input, output),
+            Err(err) => {
+                failed += 1;
+                warn!("convert {:?}: {}", input, err);
+                eprintln!("fail  {:?}: {}", input, err);
+            }
+        }
+    }
+    println!("converted {} of {} images", jobs.len() - failed, jobs.len());
+    failed == 0
+}
+
+type Job = (PathBuf, Result<PathBuf, String>);
+
+/// Pairs each input image with where it will be written
+///
+/// Directories are expanded to the images directly inside them. An input whose output name
+/// is already taken by an earlier one is reported rather than overwriting it.
+fn jobs(options: &ConvertOptions) -> Result<Vec<Job>, ConvertError> {
+    let ext = options.to.info().extensions[0];
+    let mut inputs = Vec::new();
+    for input in &options.inputs {
+        if input.is_dir() {
+            let mut files = list_dir(input, false)
+                .map_err(|err| ConvertError::new(format!("could not read {:?}: {}", input, err)))?;
+            files.retain(|f| format::is_browsable(f));
+            files.sort();
+            inputs.append(&mut files);
+        } else {
+            inputs.push(input.clone());
+        }
+    }
+
+    let mut taken = HashSet::new();
+    Ok(inputs
+        .into_iter()
+        .map(|input| {
+            let output = match input.file_stem() {
+                Some(stem) => {
+                    // not `with_extension`, which would cut `a.b` down to `a`
+                    let mut name = stem.to_os_string();
+                    name.push(".");
+                    name.push(ext);
+                    let output = options.out.join(name);
+                    if taken.insert(output.clone()) {
+                        Ok(output)
+                    } else {
+                        Err(format!(
+                            "{:?} is already the output of another input",
+                            output
+                        ))
+                    }
+                }
+                None => Err(String::from("input has no file name")),
+            };
+            (input, output)
+        })
+        .collect())
+}
+
+fn convert_file(
+    input: &Path,
+    output: &Path,
+    options: &ConvertOptions,
+    config: &Config,
+) -> Result<(), ConvertError> {
+    let image = image_loading::load_image(input).map_err(|err| ConvertError::new(err.reason()))?;
+    let mut metadata = Metadata::read(input);
+    metadata.strip(options.strip);
+    let orientation = match Orientation::read(input) {
+        Ok(orientation) if options.auto_rotate => {
+            metadata.reset_orientation();
+            orientation
+        }
+        Ok(_) => Orientation::default(),
+        Err(err) => {
+            info!("exif {:?}: {:?}", input, err);
+            Orientation::default()
+        }
+    };
+    save_image(
+        &image,
+        orientation,
+        &metadata,
+        &config.encode,
+        output,
+        config.backup,
+    )
+    .map_err(|err| ConvertError::new(err.reason()))
+}
+
+#[cfg(test)]
+mod convert_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use crate::{format::Format, metadata::Strip};
+    use log::LevelFilter;
+
+    fn options(inputs: &[&str], out: &Path) -> ConvertOptions {
+        ConvertOptions {
+            inputs: inputs.iter().map(PathBuf::from).collect(),
+            to: Format::Qoi,
+            out: out.to_path_buf(),
+            auto_rotate: true,
+            strip: Strip::None,
+            settings: Vec::new(),
+            log_level: LevelFilter::Trace,
+        }
+    }
+
+    #[test]
+    fn test_convert_files() {
+        let dir = TestDir::new("convert");
+        let out = dir.join("out");
+        let options = options(&["./test_images/dice.png", "./test_images/0.jpg"], &out);
+        assert!(run(&options, &Config::default()));
+
+        let dice = image::open(out.join("dice.qoi")).unwrap();
+        let original = image::open("./test_images/dice.png").unwrap();
+        assert_eq!(dice.width(), original.width());
+        assert_eq!(dice.height(), original.height());
+        assert!(out.join("0.qoi").is_file());
+    }
+
+    #[test]
+    fn test_failures_are_reported() {
+        let dir = TestDir::new("convert_fail");
+        let out = dir.join("out");
+        let options = options(
+            &[
+                "./test_images/not_an_image.txt",
+                "./test_images/dice.png",
+                "./test_images/dice.jpg",
+            ],
+            &out,
+        );
+        let jobs = jobs(&options).unwrap();
+        assert!(jobs[1].1.is_ok());
+        // dice.jpg would overwrite the output of dice.png
+        assert!(jobs[2].1.is_err());
+
+        assert!(!run(&options, &Config::default()));
+        assert!(out.join("dice.qoi").is_file());
+        assert!(!out.join("not_an_image.qoi").exists());
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{
+    atomic_write,
+    image_saving::{self, Encoder},
+};
+
+/// Image formats the viewer knows how to recognise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Qoi,
+    Ico,
+    Gif,
+    WebP,
+    Tiff,
+    Bmp,
+}
+
+/// Registry entry describing how to recognise and save a format
+pub struct FormatInfo {
+    pub format: Format,
+    pub name: &'static str,
+    /// Lowercase extensions, the first being the preferred one
+    pub extensions: &'static [&'static str],
+    magic: fn(&[u8]) -> bool,
+    /// How the format is written, if it can be saved
+    pub encoder: Option<Encoder>,
+}
+
+/// Number of leading bytes needed to recognise any format in [`FORMATS`]
+pub const SNIFF_LEN: usize = 12;
+
+pub const FORMATS: [FormatInfo; 8] = [
+    FormatInfo {
+        format: Format::Jpeg,
+        name: "JPEG",
+        extensions: &["jpg", "jpeg", "jpe", "jfif"],
+        magic: |b| b.starts_with(&[0xFF, 0xD8, 0xFF]),
+        encoder: Some(image_saving::encode_jpeg),
+    },
+    FormatInfo {
+        format: Format::Png,
+        name: "PNG",
+        extensions: &["png", "apng"],
+        magic: |b| b.starts_with(b"\x89PNG\r\n\x1a\n"),
+        encoder: Some(image_saving::encode_png),
+    },
+    FormatInfo {
+        format: Format::Qoi,
+        name: "QOI",
+        extensions: &["qoi"],
+        magic: |b| b.starts_with(b"qoif"),
+        encoder: Some(image_saving::encode_qoi),
+    },
+    FormatInfo {
+        format: Format::Ico,
+        name: "ICO",
+        extensions: &["ico", "cur"],
+        // The reserved word and type alone match too much, so the image count has to be set and
+        // the first directory entry's reserved byte clear
+        magic: |b| {
+            b.len() >= 10
+                && (b.starts_with(&[0, 0, 1, 0]) || b.starts_with(&[0, 0, 2, 0]))
+                && (b[4] != 0 || b[5] != 0)
+                && b[9] == 0
+        },
+        encoder: None,
+    },
+    FormatInfo {
+        format: Format::Gif,
+        name: "GIF",
+        extensions: &["gif"],
+        magic: |b| b.starts_with(b"GIF87a") || b.starts_with(b"GIF89a"),
+        encoder: Some(image_saving::encode_gif),
+    },
+    FormatInfo {
+        format: Format::WebP,
+        name: "WebP",
+        extensions: &["webp"],
+        magic: |b| b.len() >= 12 && b.starts_with(b"RIFF") && &b[8..12] == b"WEBP",
+        encoder: Some(image_saving::encode_webp),
+    },
+    FormatInfo {
+        format: Format::Tiff,
+        name: "TIFF",
+        extensions: &["tif", "tiff"],
+        magic: |b| b.starts_with(b"II*\0") || b.starts_with(b"MM\0*"),
+        encoder: Some(image_saving::encode_tiff),
+    },
+    FormatInfo {
+        format: Format::Bmp,
+        name: "BMP",
+        extensions: &["bmp", "dib"],
+        magic: |b| b.starts_with(b"BM"),
+        encoder: Some(image_saving::encode_bmp),
+    },
+];
+
+impl Format {
+    pub fn info(self) -> &'static FormatInfo {
+        FORMATS
+            .iter()
+            .find(|info| info.format == self)
+            .expect("every format has a registry entry")
+    }
+
+    /// Recognises a format from the first [`SNIFF_LEN`] bytes of a file
+    pub fn sniff(bytes: &[u8]) -> Option<Format> {
+        FORMATS
+            .iter()
+            .find(|info| (info.magic)(bytes))
+            .map(|info| info.format)
+    }
+
+    pub fn from_extension(path: &Path) -> Option<Format> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        FORMATS
+            .iter()
+            .find(|info| info.extensions.contains(&ext.as_str()))
+            .map(|info| info.format)
+    }
+
+    /// Recognises the format of the file at `path` from its contents
+    pub fn detect(path: &Path) -> io::Result<Option<Format>> {
+        let (head, len) = read_head(path)?;
+        Ok(Format::sniff(&head[..len]))
+    }
+}
+
+/// Reads up to [`SNIFF_LEN`] leading bytes of a file, returning them with how many were read
+fn read_head(path: &Path) -> io::Result<([u8; SNIFF_LEN], usize)> {
+    let mut head = [0u8; SNIFF_LEN];
+    let mut file = File::open(path)?;
+    let mut len = 0;
+    while len < SNIFF_LEN {
+        match file.read(&mut head[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok((head, len))
+}
+
+/// Whether `path` looks like an image the viewer can show
+///
+/// Every file is sniffed. One with a known extension is also let through while it is too short to
+/// tell, as it may still be being written. The backups and half written files left by saving hold
+/// images but are not to be browsed.
+pub fn is_browsable(path: &Path) -> bool {
+    if !path.is_file() || atomic_write::is_leftover(path) {
+        return false;
+    }
+    match read_head(path) {
+        Ok((head, len)) => {
+            Format::sniff(&head[..len]).is_some()
+                || (len < SNIFF_LEN && Format::from_extension(path).is_some())
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_registry_is_complete() {
+        for info in FORMATS.iter() {
+            assert_eq!(info.format.info().name, info.name);
+            assert!(!info.extensions.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_sniff_test_images() {
+        for (file, format) in [
+            ("0.jpg", Format::Jpeg),
+            ("dice.png", Format::Png),
+            ("dice.qoi", Format::Qoi),
+            ("png_indexed.png", Format::Png),
+        ] {
+            let path = format!("./test_images/{}", file);
+            assert_eq!(Format::detect(Path::new(&path)).unwrap(), Some(format));
+        }
+        assert_eq!(
+            Format::detect(Path::new("./test_images/not_an_image.txt")).unwrap(),
+            None
+        );
+        assert_eq!(
+            Format::detect(Path::new("./img/icon.ico")).unwrap(),
+            Some(Format::Ico)
+        );
+    }
+
+    #[test]
+    fn test_sniff_magic() {
+        assert_eq!(Format::sniff(b"GIF89a\x01\x00"), Some(Format::Gif));
+        assert_eq!(Format::sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some(Format::WebP));
+        assert_eq!(Format::sniff(b"RIFF\0\0\0\0WAVEfmt "), None);
+        assert_eq!(Format::sniff(b"II*\0\x08\0\0\0"), Some(Format::Tiff));
+        assert_eq!(Format::sniff(b"MM\0*\0\0\0\x08"), Some(Format::Tiff));
+        assert_eq!(Format::sniff(b"BM6\0\0\0"), Some(Format::Bmp));
+        assert_eq!(Format::sniff(b""), None);
+    }
+
+    #[test]
+    fn test_sniff_ico() {
+        assert_eq!(
+            Format::sniff(b"\0\0\x01\0\x01\0\x10\x10\0\0\x01\0"),
+            Some(Format::Ico)
+        );
+        assert_eq!(
+            Format::sniff(b"\0\0\x02\0\x02\0\x20\x20\0\0\x01\0"),
+            Some(Format::Ico)
+        );
+        // No images
+        assert_eq!(Format::sniff(b"\0\0\x01\0\0\0\x10\x10\0\0\x01\0"), None);
+        // Reserved byte of the first entry set
+        assert_eq!(Format::sniff(b"\0\0\x01\0\x01\0\x10\x10\0\x07\x01\0"), None);
+        assert_eq!(Format::sniff(b"\0\0\x01\0"), None);
+    }
+
+    #[test]
+    fn test_browsable_is_sniffed() {
+        let dir = TestDir::new("format_browsable");
+        let image = dir.join("image.ico");
+        fs::copy("./img/icon.ico", &image).unwrap();
+        let renamed = dir.join("image.dat");
+        fs::copy("./test_images/dice.png", &renamed).unwrap();
+        let fake = dir.join("fake.png");
+        fs::copy("./test_images/not_an_image.txt", &fake).unwrap();
+        let empty = dir.join("empty.jpg");
+        fs::write(&empty, b"").unwrap();
+        let zeros = dir.join("zeros.bin");
+        fs::write(&zeros, [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+
+        assert!(is_browsable(&image));
+        assert!(is_browsable(&renamed));
+        assert!(!is_browsable(&fake));
+        assert!(is_browsable(&empty));
+        assert!(!is_browsable(&zeros));
+    }
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(
+            Format::from_extension(Path::new("a/IMG_1.JPG")),
+            Some(Format::Jpeg)
+        );
+        assert_eq!(
+            Format::from_extension(Path::new("photo.jpeg")),
+            Some(Format::Jpeg)
+        );
+        assert_eq!(Format::from_extension(Path::new("notes.txt")), None);
+        assert_eq!(Format::from_extension(Path::new("noext")), None);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::atomic_write;
+
+/// The name of the file the window's last size and position are kept in, next to the config
+pub const GEOMETRY_FILE: &str = "femtophotos.window";
+
+/// Where the window was and how big it was, in physical pixels, when last not fullscreen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    /// The top left of the window, including its decorations
+    pub position: (i32, i32),
+    /// The size of the window's contents
+    pub size: (u32, u32),
+}
+
+impl Geometry {
+    /// Where the geometry is kept, if the executable's directory can be found
+    pub fn path() -> Option<PathBuf> {
+        let exe = env::current_exe().ok()?;
+        Some(exe.parent()?.join(GEOMETRY_FILE))
+    }
+
+    /// Reads the geometry saved at `path`, if there is one that makes sense
+    pub fn load(path: &Path) -> Option<Geometry> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!("geometry: {:?}: {}", path, err);
+                return None;
+            }
+        };
+        let geometry = Geometry::parse(&text);
+        if geometry.is_none() {
+            warn!(
+                "geometry: ignoring {:?}, it is not a window size and position",
+                path
+            );
+        }
+        geometry
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        atomic_write::write(path, self.contents().as_bytes(), false)
+    }
+
+    /// Reads `position = x, y` and `size = width, height` lines
+    fn parse(text: &str) -> Option<Geometry> {
+        let (mut position, mut size) = (None, None);
+        for line in text.lines() {
+            let (key, value) = line.split_once('=')?;
+            let (a, b) = value.split_once(',')?;
+            match key.trim() {
+                "position" => position = Some((a.trim().parse().ok()?, b.trim().parse().ok()?)),
+                "size" => size = Some((a.trim().parse().ok()?, b.trim().parse().ok()?)),
+                _ => return None,
+            }
+        }
+        match (position, size) {
+            (Some(position), Some(size @ (1.., 1..))) => Some(Geometry { position, size }),
+            _ => None,
+        }
+    }
+
+    fn contents(&self) -> String {
+        format!(
+            "position = {}, {}\nsize = {}, {}\n",
+            self.position.0, self.position.1, self.size.0, self.size.1
+        )
+    }
+}
+
+#[cfg(test)]
+mod geometry_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_round_trip() {
+        let geometry = Geometry {
+            position: (-1280, 40),
+            size: (800, 600),
+        };
+        assert_eq!(Geometry::parse(&geometry.contents()), Some(geometry));
+
+        let dir = TestDir::new("geometry");
+        let path = dir.join(GEOMETRY_FILE);
+        assert_eq!(Geometry::load(&path), None);
+        geometry.save(&path).unwrap();
+        assert_eq!(Geometry::load(&path), Some(geometry));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_rejects_nonsense() {
+        for bad in [
+            "",
+            "position = 0, 0",
+            "position = 0, 0\nsize = 0, 600",
+            "position = 0, 0\nsize = 800",
+            "position = 0, 0\nsize = 800, 600\nzoom = 1, 1",
+            "position = a, 0\nsize = 800, 600",
+        ] {
+            assert_eq!(Geometry::parse(bad), None, "{:?}", bad);
+        }
+    }
+}
@@ -1,22 +1,34 @@
 use std::{
-    env, fs,
+    borrow::Cow,
+    env, fmt, fs,
     io::{self, Cursor, ErrorKind},
     path::{Path, PathBuf},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use glium::texture::RawImage2d;
+use glium::texture::{ClientFormat, RawImage2d};
 use image::{
-    error::{DecodingError, ImageFormatHint},
-    Rgb, Rgba,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    error::LimitErrorKind,
+    AnimationDecoder, DynamicImage, GenericImageView, ImageBuffer, ImageError, Pixel,
 };
 use log::{debug, info, trace, warn};
 use qoi::decode_to_vec;
 use turbojpeg::decompress_image;
 
+use crate::format::Format;
+
+type Image16<P> = ImageBuffer<P, Vec<u16>>;
+
 enum Image {
     Rgb(image::RgbImage),
     Rgba(image::RgbaImage),
+    Luma(image::GrayImage),
+    LumaA(image::GrayAlphaImage),
+    Rgb16(Image16<image::Rgb<u16>>),
+    Rgba16(Image16<image::Rgba<u16>>),
+    Luma16(Image16<image::Luma<u16>>),
+    LumaA16(Image16<image::LumaA<u16>>),
 }
 
 impl Image {
@@ -24,245 +36,714 @@ impl Image {
         match self {
             Image::Rgb(img) => img.dimensions(),
             Image::Rgba(img) => img.dimensions(),
+            Image::Luma(img) => img.dimensions(),
+            Image::LumaA(img) => img.dimensions(),
+            Image::Rgb16(img) => img.dimensions(),
+            Image::Rgba16(img) => img.dimensions(),
+            Image::Luma16(img) => img.dimensions(),
+            Image::LumaA16(img) => img.dimensions(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Image::Rgb(_) => "RGB",
+            Image::Rgba(_) => "RGBA",
+            Image::Luma(_) => "L",
+            Image::LumaA(_) => "LA",
+            Image::Rgb16(_) => "RGB16",
+            Image::Rgba16(_) => "RGBA16",
+            Image::Luma16(_) => "L16",
+            Image::LumaA16(_) => "LA16",
         }
     }
 }
 
 impl std::fmt::Display for Image {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Image::Rgb(img) => write!(f, "RGB({}, {})", img.dimensions().0, img.dimensions().1),
-            Image::Rgba(img) => write!(f, "RGBA({}, {})", img.dimensions().0, img.dimensions().1),
+        let (width, height) = self.get_size();
+        write!(f, "{}({}, {})", self.name(), width, height)
+    }
+}
+
+impl From<Image> for DynamicImage {
+    fn from(img: Image) -> Self {
+        match img {
+            Image::Rgb(img) => DynamicImage::ImageRgb8(img),
+            Image::Rgba(img) => DynamicImage::ImageRgba8(img),
+            Image::Luma(img) => DynamicImage::ImageLuma8(img),
+            Image::LumaA(img) => DynamicImage::ImageLumaA8(img),
+            Image::Rgb16(img) => DynamicImage::ImageRgb16(img),
+            Image::Rgba16(img) => DynamicImage::ImageRgba16(img),
+            Image::Luma16(img) => DynamicImage::ImageLuma16(img),
+            Image::LumaA16(img) => DynamicImage::ImageLumaA16(img),
         }
     }
 }
 
-type BoxedError = Box<dyn std::error::Error>;
 type ImageDimensions = (u32, u32);
 type RawImage = Vec<u8>;
 
-pub fn load_image(path: &Path) -> Result<RawImage2d<'static, u8>, BoxedError> {
+/// The decoder that was being used when a load failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoder {
+    TurboJpeg,
+    Spng,
+    Qoi,
+    ImageCrate,
+}
+
+impl fmt::Display for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoder::TurboJpeg => write!(f, "turbojpeg"),
+            Decoder::Spng => write!(f, "spng"),
+            Decoder::Qoi => write!(f, "qoi"),
+            Decoder::ImageCrate => write!(f, "image"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// No decoder recognised the file; `decoder` is `None` when none was tried
+    UnsupportedFormat {
+        path: PathBuf,
+        decoder: Option<Decoder>,
+    },
+    /// The file was recognised but its data was truncated or invalid
+    Corrupt {
+        path: PathBuf,
+        decoder: Decoder,
+        reason: String,
+    },
+    /// The file decoded to a colour type the viewer cannot display
+    UnsupportedColourType {
+        path: PathBuf,
+        decoder: Decoder,
+        colour: String,
+    },
+    /// The image dimensions do not fit the decoded buffer or exceed a limit
+    DimensionOverflow {
+        path: PathBuf,
+        decoder: Decoder,
+        reason: String,
+    },
+    Io {
+        path: PathBuf,
+        decoder: Option<Decoder>,
+        source: io::Error,
+    },
+    /// The image failed to load and so did the `no_image.png` placeholder
+    FallbackMissing {
+        path: PathBuf,
+        source: Box<LoadError>,
+    },
+}
+
+impl LoadError {
+    pub fn path(&self) -> &Path {
+        match self {
+            LoadError::UnsupportedFormat { path, .. }
+            | LoadError::Corrupt { path, .. }
+            | LoadError::UnsupportedColourType { path, .. }
+            | LoadError::DimensionOverflow { path, .. }
+            | LoadError::Io { path, .. }
+            | LoadError::FallbackMissing { path, .. } => path,
+        }
+    }
+
+    pub fn decoder(&self) -> Option<Decoder> {
+        match self {
+            LoadError::UnsupportedFormat { decoder, .. } | LoadError::Io { decoder, .. } => {
+                *decoder
+            }
+            LoadError::Corrupt { decoder, .. }
+            | LoadError::UnsupportedColourType { decoder, .. }
+            | LoadError::DimensionOverflow { decoder, .. } => Some(*decoder),
+            LoadError::FallbackMissing { .. } => None,
+        }
+    }
+
+    /// Short, user facing description of the failure
+    pub fn reason(&self) -> String {
+        match self {
+            LoadError::UnsupportedFormat { .. } => String::from("unsupported format"),
+            LoadError::Corrupt { reason, .. } => format!("corrupt file: {}", reason),
+            LoadError::UnsupportedColourType { colour, .. } => {
+                format!("unsupported colour type: {}", colour)
+            }
+            LoadError::DimensionOverflow { reason, .. } => format!("bad dimensions: {}", reason),
+            LoadError::Io { source, .. } => source.to_string(),
+            LoadError::FallbackMissing { source, .. } => {
+                format!("{} (placeholder image missing)", source.reason())
+            }
+        }
+    }
+
+    fn io(path: &Path, decoder: Option<Decoder>, source: io::Error) -> Self {
+        LoadError::Io {
+            path: path.to_path_buf(),
+            decoder,
+            source,
+        }
+    }
+
+    fn corrupt<E: fmt::Display>(path: &Path, decoder: Decoder, reason: E) -> Self {
+        LoadError::Corrupt {
+            path: path.to_path_buf(),
+            decoder,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn from_image_error(path: &Path, err: ImageError) -> Self {
+        let decoder = Decoder::ImageCrate;
+        match err {
+            ImageError::Unsupported(_) => LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                decoder: Some(decoder),
+            },
+            ImageError::Limits(limit) => match limit.kind() {
+                LimitErrorKind::DimensionError => LoadError::DimensionOverflow {
+                    path: path.to_path_buf(),
+                    decoder,
+                    reason: limit.to_string(),
+                },
+                _ => LoadError::corrupt(path, decoder, limit),
+            },
+            ImageError::IoError(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                LoadError::corrupt(path, decoder, "truncated data")
+            }
+            ImageError::IoError(err) => LoadError::io(path, Some(decoder), err),
+            ImageError::Decoding(err) => LoadError::corrupt(path, decoder, err),
+            ImageError::Encoding(err) => LoadError::corrupt(path, decoder, err),
+            ImageError::Parameter(err) => LoadError::corrupt(path, decoder, err),
+        }
+    }
+
+    fn from_turbojpeg_error(path: &Path, err: turbojpeg::Error) -> Self {
+        match err {
+            turbojpeg::Error::IntegerOverflow(field) => LoadError::DimensionOverflow {
+                path: path.to_path_buf(),
+                decoder: Decoder::TurboJpeg,
+                reason: format!("{} overflowed", field),
+            },
+            err => LoadError::corrupt(path, Decoder::TurboJpeg, err),
+        }
+    }
+
+    fn from_spng_error(path: &Path, err: spng::Error) -> Self {
+        match err {
+            spng::Error::Signature => LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                decoder: Some(Decoder::Spng),
+            },
+            spng::Error::IoEof | spng::Error::Eof => {
+                LoadError::corrupt(path, Decoder::Spng, "truncated data")
+            }
+            spng::Error::Overflow
+            | spng::Error::Width
+            | spng::Error::Height
+            | spng::Error::UserWidth
+            | spng::Error::UserHeight => LoadError::DimensionOverflow {
+                path: path.to_path_buf(),
+                decoder: Decoder::Spng,
+                reason: err.to_string(),
+            },
+            err => LoadError::corrupt(path, Decoder::Spng, err),
+        }
+    }
+
+    fn from_qoi_error(path: &Path, err: qoi::Error) -> Self {
+        match err {
+            qoi::Error::InvalidMagic { .. } => LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                decoder: Some(Decoder::Qoi),
+            },
+            qoi::Error::UnexpectedBufferEnd => {
+                LoadError::corrupt(path, Decoder::Qoi, "truncated data")
+            }
+            qoi::Error::InvalidImageDimensions { .. } => LoadError::DimensionOverflow {
+                path: path.to_path_buf(),
+                decoder: Decoder::Qoi,
+                reason: err.to_string(),
+            },
+            qoi::Error::IoError(err) => LoadError::io(path, Some(Decoder::Qoi), err),
+            err => LoadError::corrupt(path, Decoder::Qoi, err),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.decoder() {
+            Some(decoder) => write!(
+                f,
+                "LoadError: {:?} ({}): {}",
+                self.path(),
+                decoder,
+                self.reason()
+            ),
+            None => write!(f, "LoadError: {:?}: {}", self.path(), self.reason()),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::FallbackMissing { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Loads the image at `path`, trying the fast decoders before the image crate
+///
+/// The image keeps the channel layout and bit depth it was stored with. The returned error is
+/// the most specific one reported by any of the decoders.
+pub fn load_image(path: &Path) -> Result<DynamicImage, LoadError> {
     let start = Instant::now();
 
-    let image: Image = match fast_load(path) {
-        Ok(img) => img,
-        Err(err) => {
-            warn!("fast load failed: {:?}", err);
-            match slow_load_rgb(path) {
+    let image = match fast_load(path) {
+        Ok(img) => {
+            debug!("{}", img);
+            DynamicImage::from(img)
+        }
+        Err(fast_err) => {
+            warn!("fast load failed: {}", fast_err);
+            match slow_decode(path) {
                 Ok(img) => img,
-                Err(err) => {
-                    warn!("rgb slow load failed: {:?}", err);
-                    match slow_load_rgba(path) {
-                        Ok(img) => img,
-                        Err(err) => {
-                            warn!("rgba slow load failed: {:?}", err);
-
-                            let current_exe = env::current_exe()?;
-                            let parent = match current_exe.parent() {
-                                Some(parent) => parent,
-                                None => {
-                                    return Err(Box::new(io::Error::new(
-                                        ErrorKind::NotFound,
-                                        "executable had no parent",
-                                    )))
-                                }
-                            };
-                            let path_str = format!("{}/img/no_image.png", parent.to_str().unwrap());
-                            let path = Path::new(&path_str);
-                            return load_image(path);
-                        }
-                    }
+                Err(slow_err) => {
+                    warn!("slow load failed: {}", slow_err);
+                    return Err(most_specific(fast_err, slow_err));
                 }
             }
         }
     };
 
     info!("image decompressed: {:?}", start.elapsed());
-    debug!("{}", image);
-    info!("{:?}", image.get_size());
+    info!("{:?} {:?}", image.color(), image.dimensions());
 
-    texture_from_image(image)
+    Ok(image)
 }
 
-fn fast_load(path: &Path) -> Result<Image, BoxedError> {
-    match path.extension() {
-        Some(ext) => match ext.to_ascii_lowercase().to_str() {
-            Some("jpg") | Some("jfif") => Ok(Image::Rgba(decompress_image(&(fs::read(path)?))?)),
-            Some("png") => {
-                let file = &(fs::read(path).unwrap());
-                let cursor = Cursor::new(file);
-                let decoder = spng::Decoder::new(cursor);
-                let (info, mut reader) = decoder.read_info()?;
-
-                let mut out: RawImage = vec![0; reader.output_buffer_size()];
-                reader.next_frame(&mut out).unwrap();
-
-                match info.color_type {
-                    spng::ColorType::Truecolor => Ok(Image::Rgb(rgb_image_from_raw(
-                        info.width,
-                        info.height,
-                        out,
-                        path.to_path_buf(),
-                    )?)),
-                    spng::ColorType::TruecolorAlpha => Ok(Image::Rgba(rgba_image_from_raw(
-                        info.width,
-                        info.height,
-                        out,
-                        path.to_path_buf(),
-                    )?)),
-                    _ => panic!(
-                        "not implemented grayscale png; this should probably return an error"
-                    ),
-                }
+/// The pixels of `image` as an 8 bit RGB(A) texture, borrowed when already in that layout
+///
+/// The texture is 8 bit sRGB, so everything else is converted for display.
+pub fn texture_data(image: &DynamicImage) -> RawImage2d<'_, u8> {
+    let (width, height) = image.dimensions();
+    match image {
+        DynamicImage::ImageRgb8(img) => RawImage2d {
+            data: Cow::Borrowed(img.as_raw()),
+            width,
+            height,
+            format: ClientFormat::U8U8U8,
+        },
+        DynamicImage::ImageRgba8(img) => RawImage2d {
+            data: Cow::Borrowed(img.as_raw()),
+            width,
+            height,
+            format: ClientFormat::U8U8U8U8,
+        },
+        img if img.color().has_alpha() => {
+            RawImage2d::from_raw_rgba(img.to_rgba8().into_raw(), (width, height))
+        }
+        img => RawImage2d::from_raw_rgb(img.to_rgb8().into_raw(), (width, height)),
+    }
+}
+
+pub struct AnimationFrame {
+    /// Always RGBA, whatever the file stored
+    pub image: DynamicImage,
+    pub delay: Duration,
+}
+
+/// A fully decoded image, ready to be uploaded as one or more textures
+pub enum Decoded {
+    Still(DynamicImage),
+    Animated(Vec<AnimationFrame>),
+}
+
+impl Decoded {
+    pub fn size(&self) -> ImageDimensions {
+        match self {
+            Decoded::Still(image) => image.dimensions(),
+            Decoded::Animated(frames) => frames[0].image.dimensions(),
+        }
+    }
+
+    /// Memory used by the decoded pixels
+    pub fn byte_size(&self) -> usize {
+        match self {
+            Decoded::Still(image) => image.as_bytes().len(),
+            Decoded::Animated(frames) => frames.iter().map(|f| f.image.as_bytes().len()).sum(),
+        }
+    }
+
+    /// The image shown as frame `index`, which is the image itself for stills
+    pub fn frame(&self, index: usize) -> &DynamicImage {
+        match self {
+            Decoded::Still(image) => image,
+            Decoded::Animated(frames) => &frames[index].image,
+        }
+    }
+}
+
+/// Decodes every frame of the image at `path`
+pub fn decode(path: &Path) -> Result<Decoded, LoadError> {
+    match load_animation(path) {
+        Ok(Some(frames)) => return Ok(Decoded::Animated(frames)),
+        Ok(None) => (),
+        Err(err) => warn!("animation: {}", err),
+    }
+    load_image(path).map(Decoded::Still)
+}
+
+/// Frames shorter than this are shown for [`DEFAULT_FRAME_DELAY`], as browsers do
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Decodes every frame of an animated GIF, APNG or WebP
+///
+/// Returns `None` for formats that cannot animate and for animations with a single frame, which
+/// are left to [`load_image`].
+pub fn load_animation(path: &Path) -> Result<Option<Vec<AnimationFrame>>, LoadError> {
+    let format = Format::detect(path).map_err(|err| LoadError::io(path, None, err))?;
+    let open = || {
+        fs::File::open(path)
+            .map(io::BufReader::new)
+            .map_err(|err| LoadError::io(path, Some(Decoder::ImageCrate), err))
+    };
+    let image_err = |err| LoadError::from_image_error(path, err);
+
+    let start = Instant::now();
+    let frames = match format {
+        Some(Format::Gif) => GifDecoder::new(open()?)
+            .map_err(image_err)?
+            .into_frames()
+            .collect_frames(),
+        Some(Format::Png) => {
+            let decoder = PngDecoder::new(open()?).map_err(image_err)?;
+            if !decoder.is_apng() {
+                return Ok(None);
             }
-            Some("qoi") => {
-                let file = &(fs::read(path).unwrap());
-                let (header, decoded) = decode_to_vec(file)?;
-
-                match header.channels {
-                    qoi::Channels::Rgb => Ok(Image::Rgb(rgb_image_from_raw(
-                        header.width,
-                        header.height,
-                        decoded,
-                        path.to_path_buf(),
-                    )?)),
-                    qoi::Channels::Rgba => Ok(Image::Rgba(rgba_image_from_raw(
-                        header.width,
-                        header.height,
-                        decoded,
-                        path.to_path_buf(),
-                    )?)),
-                }
+            decoder.apng().into_frames().collect_frames()
+        }
+        Some(Format::WebP) => {
+            let decoder = WebPDecoder::new(open()?).map_err(image_err)?;
+            if !decoder.has_animation() {
+                return Ok(None);
             }
-            _ => Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                "unsupported extension",
-            ))),
-        },
-        _ => {
-            warn!("no extension");
-            Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                "unsupported extension",
-            )))
+            decoder.into_frames().collect_frames()
         }
+        _ => return Ok(None),
+    }
+    .map_err(image_err)?;
+
+    if frames.len() < 2 {
+        return Ok(None);
     }
+    info!(
+        "{} frames decompressed: {:?}",
+        frames.len(),
+        start.elapsed()
+    );
+
+    Ok(Some(
+        frames
+            .into_iter()
+            .map(|frame| {
+                let delay = Duration::from(frame.delay());
+                AnimationFrame {
+                    image: DynamicImage::ImageRgba8(frame.into_buffer()),
+                    delay: if delay < MIN_FRAME_DELAY {
+                        DEFAULT_FRAME_DELAY
+                    } else {
+                        delay
+                    },
+                }
+            })
+            .collect(),
+    ))
 }
 
-fn slow_load_rgb(path: &Path) -> Result<Image, BoxedError> {
-    let reader = image::io::Reader::open(path)?.with_guessed_format()?;
-    trace!("detected format: {:?}", reader.format());
-    let decoded = reader.decode()?;
-    let data = match decoded.as_rgb8() {
-        Some(data) => data,
-        None => {
-            return Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                "unsupported extension",
-            )))
+/// Loads the `no_image.png` placeholder shown when an image fails to open
+///
+/// `cause` is handed back on success so the caller can still report it.
+pub fn load_fallback(cause: LoadError) -> Result<(DynamicImage, LoadError), LoadError> {
+    let path = match exe_relative("img/no_image.png") {
+        Ok(path) => path,
+        Err(err) => {
+            return Err(LoadError::FallbackMissing {
+                path: err.path().to_path_buf(),
+                source: Box::new(cause),
+            })
         }
     };
-    Ok(Image::Rgb(data.to_owned()))
+    match load_image(&path) {
+        Ok(image) => Ok((image, cause)),
+        Err(_) => Err(LoadError::FallbackMissing {
+            path,
+            source: Box::new(cause),
+        }),
+    }
 }
 
-fn slow_load_rgba(path: &Path) -> Result<Image, BoxedError> {
-    let reader = image::io::Reader::open(path)?.with_guessed_format()?;
-    trace!("detected format: {:?}", reader.format());
-    let decoded = reader.decode()?;
-    let data = match decoded.as_rgba8() {
-        Some(data) => data,
+/// Picks the error that best explains why the file could not be opened
+fn most_specific(fast_err: LoadError, slow_err: LoadError) -> LoadError {
+    match (&fast_err, &slow_err) {
+        (LoadError::UnsupportedFormat { decoder: None, .. }, _) => slow_err,
+        (_, LoadError::UnsupportedFormat { .. }) => fast_err,
+        (_, LoadError::Io { .. }) => fast_err,
+        _ => slow_err,
+    }
+}
+
+fn exe_relative(file: &str) -> Result<PathBuf, LoadError> {
+    let current_exe =
+        env::current_exe().map_err(|err| LoadError::io(Path::new(file), None, err))?;
+    match current_exe.parent() {
+        Some(parent) => Ok(parent.join(file)),
+        None => Err(LoadError::io(
+            &current_exe,
+            None,
+            io::Error::new(ErrorKind::NotFound, "executable had no parent"),
+        )),
+    }
+}
+
+fn fast_load(path: &Path) -> Result<Image, LoadError> {
+    let file = fs::read(path).map_err(|err| LoadError::io(path, None, err))?;
+    let format = match Format::sniff(&file) {
+        Some(format) => format,
         None => {
-            return Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                "unsupported extension",
-            )))
+            warn!("unrecognised file contents");
+            return Err(LoadError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                decoder: None,
+            });
         }
     };
-    Ok(Image::Rgba(data.to_owned()))
+    trace!("sniffed format: {}", format.info().name);
+
+    match format {
+        Format::Jpeg => {
+            let to_load_error = |err| LoadError::from_turbojpeg_error(path, err);
+            let header = turbojpeg::read_header(&file).map_err(to_load_error)?;
+            // Grayscale is kept to one channel like grayscale PNGs, rather than tripled into RGB
+            if matches!(header.colorspace, turbojpeg::Colorspace::Gray) {
+                Ok(Image::Luma(decompress_image(&file).map_err(to_load_error)?))
+            } else {
+                Ok(Image::Rgb(decompress_image(&file).map_err(to_load_error)?))
+            }
+        }
+        Format::Png => spng_load(path, &file),
+        Format::Qoi => {
+            let (header, decoded) =
+                decode_to_vec(&file).map_err(|err| LoadError::from_qoi_error(path, err))?;
+
+            match header.channels {
+                qoi::Channels::Rgb => Ok(Image::Rgb(image_from_raw(
+                    header.width,
+                    header.height,
+                    decoded,
+                    path,
+                    Decoder::Qoi,
+                )?)),
+                qoi::Channels::Rgba => Ok(Image::Rgba(image_from_raw(
+                    header.width,
+                    header.height,
+                    decoded,
+                    path,
+                    Decoder::Qoi,
+                )?)),
+            }
+        }
+        _ => Err(LoadError::UnsupportedFormat {
+            path: path.to_path_buf(),
+            decoder: None,
+        }),
+    }
 }
 
-fn rgb_image_from_raw(
-    width: u32,
-    height: u32,
-    data: RawImage,
-    ext: PathBuf,
-) -> Result<image::RgbImage, image::ImageError> {
-    match image::ImageBuffer::<Rgb<u8>, RawImage>::from_raw(width, height, data) {
-        Some(image) => Ok(image),
-        None => Err(image::ImageError::Decoding(DecodingError::new(
-            ImageFormatHint::PathExtension(ext),
-            "Raw image bytes did not fit the image container",
-        ))),
+/// Decodes a PNG with spng, keeping its channel layout and bit depth
+///
+/// Indexed images are expanded to RGB(A) and a tRNS chunk, when present, is applied as an
+/// alpha channel. Grayscale images below 8 bits are widened to 8 bits.
+fn spng_load(path: &Path, file: &[u8]) -> Result<Image, LoadError> {
+    let spng_err = |err| LoadError::from_spng_error(path, err);
+
+    let (header, header_reader) = spng::Decoder::new(Cursor::new(file))
+        .read_info()
+        .map_err(spng_err)?;
+    let has_trns = header_reader.raw_context().get_trns().is_ok();
+    let sixteen = header.bit_depth == spng::BitDepth::Sixteen;
+
+    let format = match (header.color_type, sixteen, has_trns) {
+        (spng::ColorType::Indexed, _, false) => spng::Format::Rgb8,
+        (spng::ColorType::Indexed, _, true) => spng::Format::Rgba8,
+        (spng::ColorType::Grayscale, false, false) => spng::Format::G8,
+        (spng::ColorType::Grayscale, false, true) => spng::Format::Ga8,
+        (spng::ColorType::Grayscale, true, true) => spng::Format::Ga16,
+        (spng::ColorType::Truecolor, false, true) => spng::Format::Rgba8,
+        (spng::ColorType::Truecolor, true, true) => spng::Format::Rgba16,
+        _ => spng::Format::Png,
+    };
+    let flags = if has_trns {
+        spng::DecodeFlags::TRANSPARENCY
+    } else {
+        spng::DecodeFlags::empty()
+    };
+    trace!(
+        "spng: {:?} {:?} trns:{} -> {:?}",
+        header.color_type,
+        header.bit_depth,
+        has_trns,
+        format
+    );
+
+    let (info, mut reader) = spng::Decoder::new(Cursor::new(file))
+        .with_output_format(format)
+        .with_decode_flags(flags)
+        .read_info()
+        .map_err(spng_err)?;
+
+    let mut out: RawImage = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut out).map_err(spng_err)?;
+
+    let (width, height) = (info.width, info.height);
+    let decoder = Decoder::Spng;
+    match (info.color_type, info.bit_depth) {
+        (spng::ColorType::Truecolor, spng::BitDepth::Eight) => Ok(Image::Rgb(image_from_raw(
+            width, height, out, path, decoder,
+        )?)),
+        (spng::ColorType::TruecolorAlpha, spng::BitDepth::Eight) => Ok(Image::Rgba(
+            image_from_raw(width, height, out, path, decoder)?,
+        )),
+        (spng::ColorType::Grayscale, spng::BitDepth::Eight) => Ok(Image::Luma(image_from_raw(
+            width, height, out, path, decoder,
+        )?)),
+        (spng::ColorType::GrayscaleAlpha, spng::BitDepth::Eight) => Ok(Image::LumaA(
+            image_from_raw(width, height, out, path, decoder)?,
+        )),
+        (spng::ColorType::Truecolor, spng::BitDepth::Sixteen) => Ok(Image::Rgb16(image_from_raw(
+            width,
+            height,
+            host_u16(&out),
+            path,
+            decoder,
+        )?)),
+        (spng::ColorType::TruecolorAlpha, spng::BitDepth::Sixteen) => Ok(Image::Rgba16(
+            image_from_raw(width, height, host_u16(&out), path, decoder)?,
+        )),
+        (spng::ColorType::Grayscale, spng::BitDepth::Sixteen) => Ok(Image::Luma16(image_from_raw(
+            width,
+            height,
+            host_u16(&out),
+            path,
+            decoder,
+        )?)),
+        (spng::ColorType::GrayscaleAlpha, spng::BitDepth::Sixteen) => Ok(Image::LumaA16(
+            image_from_raw(width, height, host_u16(&out), path, decoder)?,
+        )),
+        (colour, depth) => Err(LoadError::UnsupportedColourType {
+            path: path.to_path_buf(),
+            decoder,
+            colour: format!("{:?} at {} bits", colour, depth as u8),
+        }),
+    }
+}
+
+/// Reinterprets spng's host-endian 16 bit output as samples
+fn host_u16(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+fn slow_decode(path: &Path) -> Result<image::DynamicImage, LoadError> {
+    let reader = image::io::Reader::open(path)
+        .map_err(|err| LoadError::io(path, Some(Decoder::ImageCrate), err))?
+        .with_guessed_format()
+        .map_err(|err| LoadError::io(path, Some(Decoder::ImageCrate), err))?;
+    trace!("detected format: {:?}", reader.format());
+    if reader.format().is_none() {
+        return Err(LoadError::UnsupportedFormat {
+            path: path.to_path_buf(),
+            decoder: Some(Decoder::ImageCrate),
+        });
     }
+    reader
+        .decode()
+        .map_err(|err| LoadError::from_image_error(path, err))
 }
 
-fn rgba_image_from_raw(
+fn image_from_raw<P: Pixel>(
     width: u32,
     height: u32,
-    data: RawImage,
-    ext: PathBuf,
-) -> Result<image::RgbaImage, image::ImageError> {
-    match image::ImageBuffer::<Rgba<u8>, RawImage>::from_raw(width, height, data) {
+    data: Vec<P::Subpixel>,
+    path: &Path,
+    decoder: Decoder,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, LoadError> {
+    check_raw_len(
+        width,
+        height,
+        P::CHANNEL_COUNT as usize,
+        data.len(),
+        path,
+        decoder,
+    )?;
+    match ImageBuffer::<P, Vec<P::Subpixel>>::from_raw(width, height, data) {
         Some(image) => Ok(image),
-        None => Err(image::ImageError::Decoding(DecodingError::new(
-            ImageFormatHint::PathExtension(ext),
-            "Raw image bytes did not fit the image container",
-        ))),
+        None => Err(LoadError::corrupt(
+            path,
+            decoder,
+            "raw image bytes did not fit the image container",
+        )),
     }
 }
 
-fn texture_from_image(img: Image) -> Result<RawImage2d<'static, u8>, BoxedError> {
-    match img {
-        Image::Rgb(img) => {
-            let image_dimensions = img.dimensions();
-            info!("image dimensions: {:?}", image_dimensions);
-            let buffer = img.into_raw();
-            info!("image buffer generated");
-            let r = Ok(glium::texture::RawImage2d::from_raw_rgb(
-                buffer,
-                image_dimensions,
-            ));
-            info!("image acquired");
-            r
-        }
-        Image::Rgba(img) => {
-            let image_dimensions = img.dimensions();
-            info!("image dimensions: {:?}", image_dimensions);
-            let buffer = img.into_raw();
-            info!("image buffer generated");
-            let r = Ok(glium::texture::RawImage2d::from_raw_rgba(
-                buffer,
-                image_dimensions,
-            ));
-            info!("image acquired");
-            r
-        }
+/// Checks that a decoder produced exactly `width * height * channels` samples
+fn check_raw_len(
+    width: u32,
+    height: u32,
+    channels: usize,
+    len: usize,
+    path: &Path,
+    decoder: Decoder,
+) -> Result<(), LoadError> {
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels));
+    match expected {
+        None => Err(LoadError::DimensionOverflow {
+            path: path.to_path_buf(),
+            decoder,
+            reason: format!("{}x{}x{} overflows", width, height, channels),
+        }),
+        Some(expected) if expected != len => Err(LoadError::corrupt(
+            path,
+            decoder,
+            format!("decoded {} samples, expected {}", len, expected),
+        )),
+        Some(_) => Ok(()),
     }
 }
 
-pub fn icon() -> Result<(RawImage, ImageDimensions), BoxedError> {
-    let current_exe = env::current_exe()?;
-    let parent = match current_exe.parent() {
-        Some(parent) => parent,
-        None => {
-            return Err(Box::new(io::Error::new(
-                ErrorKind::NotFound,
-                "executable had no parent",
-            )))
-        }
-    };
-    let path_str = format!("{}/img/icon.ico", parent.to_str().unwrap());
-    let path = Path::new(&path_str);
-    let reader = image::io::Reader::open(path)?.with_guessed_format()?;
-    trace!("detected format: {:?}", reader.format());
-    let decoded = reader.decode()?;
+pub fn icon() -> Result<(RawImage, ImageDimensions), LoadError> {
+    let path = exe_relative("img/icon.ico")?;
+    let decoded = slow_decode(&path)?;
     let data = match decoded.as_rgba8() {
         Some(data) => data,
         None => {
-            return Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                "unsupported extension",
-            )))
+            return Err(LoadError::UnsupportedColourType {
+                path,
+                decoder: Decoder::ImageCrate,
+                colour: format!("{:?}", decoded.color()),
+            })
         }
     };
 
@@ -272,6 +753,8 @@ pub fn icon() -> Result<(RawImage, ImageDimensions), BoxedError> {
 #[cfg(test)]
 mod image_loading_tests {
     use super::*;
+    use crate::test_dir::TestDir;
+    use image::ColorType;
 
     const IMAGES: [&str; 8] = [
         "0",
@@ -296,10 +779,8 @@ mod image_loading_tests {
             String::from("testcard_rgba"),
             String::from("wikipedia_008"),
         ];
-        let mut i = 0;
-        for image in IMAGES {
+        for (i, image) in IMAGES.into_iter().enumerate() {
             assert_eq!(images.get(i).unwrap(), &image);
-            i += 1;
         }
     }
 
@@ -308,6 +789,37 @@ mod image_loading_tests {
         assert!(load_image(Path::new("./test_images/0.jpg")).is_ok());
     }
 
+    #[test]
+    fn test_missing_file_is_io_error() {
+        let result = load_image(Path::new("./test_images/does_not_exist.png"));
+        assert!(matches!(result, Err(LoadError::Io { .. })));
+    }
+
+    #[test]
+    fn test_truncated_png_is_corrupt() {
+        let path = Path::new("./test_images/truncated.png");
+        match load_image(path) {
+            Err(LoadError::Corrupt { path: err_path, .. }) => assert_eq!(err_path, path),
+            other => panic!("expected Corrupt, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_unknown_format_is_unsupported() {
+        let result = load_image(Path::new("./test_images/not_an_image.txt"));
+        assert!(matches!(result, Err(LoadError::UnsupportedFormat { .. })));
+    }
+
+    #[test]
+    fn test_misnamed_files_are_sniffed() {
+        let dir = TestDir::new("sniff");
+        for name in ["qoi_logo.jpg", "qoi_logo"] {
+            let path = dir.join(name);
+            fs::copy("./test_images/qoi_logo.png", &path).unwrap();
+            assert!(fast_load(&path).is_ok());
+        }
+    }
+
     #[test]
     fn test_png_load() {
         for image in IMAGES {
@@ -318,6 +830,170 @@ mod image_loading_tests {
         }
     }
 
+    fn spng_fixture(name: &str) -> Image {
+        let formatted = format!("./test_images/{}.png", name);
+        let path = Path::new(&formatted);
+        spng_load(path, &fs::read(path).unwrap()).unwrap()
+    }
+
+    fn rgba_at(img: &image::RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        img.get_pixel(x, y).0
+    }
+
+    fn to_rgba(img: Image) -> image::RgbaImage {
+        DynamicImage::from(img).into_rgba8()
+    }
+
+    #[test]
+    fn test_png_grayscale() {
+        let img = spng_fixture("png_gray8");
+        assert!(matches!(img, Image::Luma(_)));
+        assert_eq!(img.get_size(), (64, 48));
+        assert_eq!(rgba_at(&to_rgba(img), 10, 3), [40, 40, 40, 255]);
+    }
+
+    #[test]
+    fn test_png_grayscale_low_bit_depth() {
+        let img = spng_fixture("png_gray4");
+        assert!(matches!(img, Image::Luma(_)));
+        assert_eq!(rgba_at(&to_rgba(img), 15, 0), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_png_grayscale_16() {
+        match spng_fixture("png_gray16") {
+            Image::Luma16(img) => assert_eq!(img.get_pixel(3, 7).0, [3 * 1024 + 7]),
+            other => panic!("expected Luma16, got {}", other),
+        }
+    }
+
+    #[test]
+    fn test_png_grayscale_trns() {
+        let img = spng_fixture("png_gray8_trns");
+        assert!(matches!(img, Image::LumaA(_)));
+        let rgba = to_rgba(img);
+        assert_eq!(rgba_at(&rgba, 0, 0), [0, 0, 0, 0]);
+        assert_eq!(rgba_at(&rgba, 1, 0), [4, 4, 4, 255]);
+    }
+
+    #[test]
+    fn test_png_grayscale_alpha() {
+        let img = spng_fixture("png_gray_alpha8");
+        assert!(matches!(img, Image::LumaA(_)));
+        assert_eq!(rgba_at(&to_rgba(img), 2, 4), [8, 8, 8, 20]);
+
+        match spng_fixture("png_gray_alpha16") {
+            Image::LumaA16(img) => assert_eq!(img.get_pixel(1, 2).0, [1024 + 2, 2000]),
+            other => panic!("expected LumaA16, got {}", other),
+        }
+    }
+
+    #[test]
+    fn test_png_indexed() {
+        let img = spng_fixture("png_indexed");
+        assert!(matches!(img, Image::Rgb(_)));
+        assert_eq!(rgba_at(&to_rgba(img), 4, 0), [16, 239, 40, 255]);
+    }
+
+    #[test]
+    fn test_png_indexed_trns() {
+        let img = spng_fixture("png_indexed_trns");
+        assert!(matches!(img, Image::Rgba(_)));
+        let rgba = to_rgba(img);
+        assert_eq!(rgba_at(&rgba, 0, 0), [0, 255, 0, 0]);
+        assert_eq!(rgba_at(&rgba, 4, 0), [16, 239, 40, 128]);
+        assert_eq!(rgba_at(&rgba, 8, 0), [32, 223, 80, 255]);
+    }
+
+    #[test]
+    fn test_png_16_bit_colour() {
+        match spng_fixture("png_rgb16") {
+            Image::Rgb16(img) => assert_eq!(img.get_pixel(2, 1).0, [2049, 0x8000, 0xffff - 2049]),
+            other => panic!("expected Rgb16, got {}", other),
+        }
+        match spng_fixture("png_rgba16") {
+            Image::Rgba16(img) => {
+                assert_eq!(img.get_pixel(2, 1).0, [2049, 0x8000, 0xffff - 2049, 1000])
+            }
+            other => panic!("expected Rgba16, got {}", other),
+        }
+    }
+
+    #[test]
+    fn test_png_fixtures_load() {
+        for image in [
+            "png_gray8",
+            "png_gray4",
+            "png_gray16",
+            "png_gray8_trns",
+            "png_gray_alpha8",
+            "png_gray_alpha16",
+            "png_indexed",
+            "png_indexed_trns",
+            "png_rgb16",
+            "png_rgba16",
+        ] {
+            let formatted = format!("./test_images/{}.png", image);
+            let result = load_image(Path::new(&formatted)).unwrap();
+            assert_eq!(result.dimensions(), (64, 48));
+        }
+    }
+
+    #[test]
+    fn test_load_keeps_layout_and_depth() {
+        for (image, colour) in [
+            ("dice.jpg", ColorType::Rgb8),
+            ("testcard_rgba.png", ColorType::Rgba8),
+            ("png_gray8.png", ColorType::L8),
+            ("png_rgb16.png", ColorType::Rgb16),
+            ("png_gray_alpha16.png", ColorType::La16),
+        ] {
+            let formatted = format!("./test_images/{}", image);
+            let result = load_image(Path::new(&formatted)).unwrap();
+            assert_eq!(result.color(), colour, "{}", image);
+        }
+    }
+
+    #[test]
+    fn test_texture_data() {
+        let rgb = load_image(Path::new("./test_images/dice.jpg")).unwrap();
+        let texture = texture_data(&rgb);
+        assert!(matches!(texture.data, Cow::Borrowed(_)));
+        assert_eq!(texture.format, ClientFormat::U8U8U8);
+
+        let gray_alpha = load_image(Path::new("./test_images/png_gray_alpha16.png")).unwrap();
+        let texture = texture_data(&gray_alpha);
+        assert_eq!(texture.format, ClientFormat::U8U8U8U8);
+        assert_eq!(texture.data.len(), 64 * 48 * 4);
+    }
+
+    #[test]
+    fn test_gif_animation() {
+        let frames = load_animation(Path::new("./test_images/anim.gif"))
+            .unwrap()
+            .unwrap();
+        let delays: Vec<u128> = frames.iter().map(|f| f.delay.as_millis()).collect();
+        assert_eq!(delays, [100, 200, 300, 400]);
+        assert!(frames.iter().all(|f| f.image.dimensions() == (32, 24)));
+    }
+
+    #[test]
+    fn test_apng_animation() {
+        let frames = load_animation(Path::new("./test_images/anim.png"))
+            .unwrap()
+            .unwrap();
+        let delays: Vec<u128> = frames.iter().map(|f| f.delay.as_millis()).collect();
+        assert_eq!(delays, [50, 100, 150]);
+    }
+
+    #[test]
+    fn test_still_images_are_not_animations() {
+        for image in ["dice.png", "dice.jpg", "dice.qoi"] {
+            let formatted = format!("./test_images/{}", image);
+            assert!(load_animation(Path::new(&formatted)).unwrap().is_none());
+        }
+    }
+
     #[test]
     fn test_jpg_load() {
         for image in IMAGES {
@@ -328,6 +1004,22 @@ mod image_loading_tests {
         }
     }
 
+    #[test]
+    fn test_jpg_grayscale() {
+        let dir = TestDir::new("jpeg_gray");
+        let path = dir.join("gray.jpg");
+        let gray = image::GrayImage::from_fn(64, 48, |x, _| image::Luma([(x * 4) as u8]));
+        gray.save(&path).unwrap();
+
+        let img = fast_load(&path).unwrap();
+        assert!(matches!(img, Image::Luma(_)));
+        assert_eq!(img.get_size(), (64, 48));
+        assert!(matches!(
+            fast_load(Path::new("./test_images/dice.jpg")).unwrap(),
+            Image::Rgb(_)
+        ));
+    }
+
     #[test]
     fn test_qoi_load() {
         for image in IMAGES {
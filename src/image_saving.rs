@@ -1,59 +1,864 @@
 use std::{
-    borrow::Cow,
-    fs::{self, File},
-    io::{BufWriter, Write},
-    path::Path,
+    fmt,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
-use glium::texture::Texture2dDataSink;
+use image::{
+    codecs::webp::{WebPEncoder, WebPQuality},
+    ColorType, DynamicImage, ImageOutputFormat,
+};
 use log::info;
 use qoi::encode_to_vec;
 use turbojpeg::compress_image;
 
-pub struct RGBAImageData {
-    pub data: Vec<(u8, u8, u8, u8)>,
-    pub width: u32,
-    pub height: u32,
+use crate::{
+    atomic_write,
+    format::{Format, FORMATS},
+    metadata::Metadata,
+    rotation::Orientation,
+};
+
+/// Encodes an image, already turned the way it is saved, into the contents of a file
+///
+/// Each format that can be saved names its encoder in [`FORMATS`].
+pub type Encoder = fn(&DynamicImage, &Metadata, &EncodeOptions) -> Result<Vec<u8>, String>;
+
+/// JPEG chroma subsampling, named by its J:a:b ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsampling {
+    /// Full resolution colour
+    S444,
+    /// Half horizontal colour resolution
+    S422,
+    /// Half horizontal and vertical colour resolution
+    S420,
 }
 
-impl Texture2dDataSink<(u8, u8, u8, u8)> for RGBAImageData {
-    fn from_raw(data: Cow<'_, [(u8, u8, u8, u8)]>, width: u32, height: u32) -> Self {
-        RGBAImageData {
-            data: data.into_owned(),
-            width,
-            height,
+impl Subsampling {
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsampling::S444 => "444",
+            Subsampling::S422 => "422",
+            Subsampling::S420 => "420",
         }
     }
+
+    fn subsamp(self) -> turbojpeg::Subsamp {
+        match self {
+            Subsampling::S444 => turbojpeg::Subsamp::None,
+            Subsampling::S422 => turbojpeg::Subsamp::Sub2x1,
+            Subsampling::S420 => turbojpeg::Subsamp::Sub2x2,
+        }
+    }
+}
+
+impl FromStr for Subsampling {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Subsampling::S444, Subsampling::S422, Subsampling::S420]
+            .into_iter()
+            .find(|subsampling| subsampling.name() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub fn name(self) -> &'static str {
+        match self {
+            PngCompression::Fast => "fast",
+            PngCompression::Default => "default",
+            PngCompression::Best => "best",
+        }
+    }
+}
+
+impl FromStr for PngCompression {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            PngCompression::Fast,
+            PngCompression::Default,
+            PngCompression::Best,
+        ]
+        .into_iter()
+        .find(|compression| compression.name() == s)
+        .ok_or(())
+    }
+}
+
+/// The filter applied to each row before compression, or the best per row when adaptive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
+}
+
+impl PngFilter {
+    pub fn name(self) -> &'static str {
+        match self {
+            PngFilter::None => "none",
+            PngFilter::Sub => "sub",
+            PngFilter::Up => "up",
+            PngFilter::Avg => "avg",
+            PngFilter::Paeth => "paeth",
+            PngFilter::Adaptive => "adaptive",
+        }
+    }
+}
+
+impl FromStr for PngFilter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            PngFilter::None,
+            PngFilter::Sub,
+            PngFilter::Up,
+            PngFilter::Avg,
+            PngFilter::Paeth,
+            PngFilter::Adaptive,
+        ]
+        .into_iter()
+        .find(|filter| filter.name() == s)
+        .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngDepth {
+    /// 16 bit images stay 16 bit
+    Keep,
+    Eight,
+}
+
+impl PngDepth {
+    pub fn name(self) -> &'static str {
+        match self {
+            PngDepth::Keep => "keep",
+            PngDepth::Eight => "8",
+        }
+    }
+}
+
+impl FromStr for PngDepth {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [PngDepth::Keep, PngDepth::Eight]
+            .into_iter()
+            .find(|depth| depth.name() == s)
+            .ok_or(())
+    }
 }
 
-pub fn save_image(data: Vec<u8>, width: u32, height: u32, path: &Path) {
-    match path.extension() {
-        Some(ext) => match ext.to_ascii_lowercase().to_str() {
-            Some("jpg") => {
-                let image = image::RgbaImage::from_vec(width, height, data).unwrap();
-                let jpg = compress_image(&image, 100, turbojpeg::Subsamp::None).unwrap();
-                fs::write(path, &jpg).unwrap();
-                info!("image saved at {:?}", path);
+/// How a PNG describes its colour space, an sRGB chunk being left out for an ICC profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngColour {
+    None,
+    /// A gAMA chunk of 1/2.2
+    Gamma,
+    /// An sRGB chunk, with the matching gAMA and cHRM chunks
+    Srgb,
+}
+
+impl PngColour {
+    pub fn name(self) -> &'static str {
+        match self {
+            PngColour::None => "none",
+            PngColour::Gamma => "gamma",
+            PngColour::Srgb => "srgb",
+        }
+    }
+}
+
+impl FromStr for PngColour {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [PngColour::None, PngColour::Gamma, PngColour::Srgb]
+            .into_iter()
+            .find(|colour| colour.name() == s)
+            .ok_or(())
+    }
+}
+
+/// Settings for the encoders used by [`save_image`]
+///
+/// QOI, TIFF, BMP and GIF have no settings of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// From 1 to 100
+    pub jpeg_quality: u8,
+    pub jpeg_subsampling: Subsampling,
+    pub jpeg_progressive: bool,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    pub png_depth: PngDepth,
+    pub png_colour: PngColour,
+    /// From 0 to 100, or `None` for lossless
+    pub webp_quality: Option<u8>,
+}
+
+/// Writes JPEGs and PNGs as they were before the encoders could be configured
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 100,
+            jpeg_subsampling: Subsampling::S444,
+            jpeg_progressive: false,
+            png_compression: PngCompression::Default,
+            png_filter: PngFilter::Sub,
+            png_depth: PngDepth::Keep,
+            png_colour: PngColour::Gamma,
+            webp_quality: Some(90),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// The path's extension is missing or names a format that cannot be written
+    UnsupportedFormat {
+        path: PathBuf,
+    },
+    /// The encoder rejected the image
+    Encode {
+        path: PathBuf,
+        format: Format,
+        reason: String,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl SaveError {
+    pub fn path(&self) -> &Path {
+        match self {
+            SaveError::UnsupportedFormat { path }
+            | SaveError::Encode { path, .. }
+            | SaveError::Io { path, .. } => path,
+        }
+    }
+
+    /// Short, user facing description of the failure
+    pub fn reason(&self) -> String {
+        match self {
+            SaveError::UnsupportedFormat { path } => match path.extension() {
+                Some(ext) => format!("cannot save .{} files", ext.to_string_lossy()),
+                None => String::from("no file extension"),
+            },
+            SaveError::Encode { format, reason, .. } => {
+                format!("{} encoder failed: {}", format.info().name, reason)
             }
-            Some("png") => {
-                let buf = &mut BufWriter::new(File::create(path).unwrap());
+            SaveError::Io { source, .. } => source.to_string(),
+        }
+    }
+
+    fn io(path: &Path, source: io::Error) -> Self {
+        SaveError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    fn encode<E: fmt::Display>(path: &Path, format: Format, reason: E) -> Self {
+        SaveError::Encode {
+            path: path.to_path_buf(),
+            format,
+            reason: reason.to_string(),
+        }
+    }
+}
 
-                let mut encoder = png::Encoder::new(buf, width, height);
-                encoder.set_color(png::ColorType::Rgba);
-                encoder.set_depth(png::BitDepth::Eight);
-                encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SaveError: {:?}: {}", self.path(), self.reason())
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Formats [`save_image`] can write, in the order of [`FORMATS`]
+pub fn save_formats() -> impl Iterator<Item = Format> {
+    FORMATS
+        .iter()
+        .filter(|info| info.encoder.is_some())
+        .map(|info| info.format)
+}
+
+/// The format a file at `path` would be saved in, judged by its extension
+pub fn save_format(path: &Path) -> Option<Format> {
+    Format::from_extension(path).filter(|format| format.info().encoder.is_some())
+}
 
-                let mut writer = encoder.write_header().unwrap();
-                writer.write_image_data(&data).unwrap();
-                info!("image saved at {:?}", path);
+/// Adds the preferred extension of `format` to a path that has none
+pub fn with_default_extension(path: PathBuf, format: Format) -> PathBuf {
+    if path.extension().is_some() {
+        path
+    } else {
+        path.with_extension(format.info().extensions[0])
+    }
+}
+
+/// Encodes `image`, turned to `orientation`, into the format named by the extension of `path`
+///
+/// The channel layout and bit depth are kept where the format can store them, otherwise the
+/// image is converted to the closest layout it can. `metadata` is written as given to formats
+/// that can hold it, so callers baking in an EXIF orientation should reset it first. The file
+/// is replaced atomically, keeping the previous one as a `.bak` with `backup`.
+pub fn save_image(
+    image: &DynamicImage,
+    orientation: Orientation,
+    metadata: &Metadata,
+    options: &EncodeOptions,
+    path: &Path,
+    backup: bool,
+) -> Result<(), SaveError> {
+    let (format, encoder) = save_format(path)
+        .and_then(|format| Some((format, format.info().encoder?)))
+        .ok_or_else(|| SaveError::UnsupportedFormat {
+            path: path.to_path_buf(),
+        })?;
+    let oriented;
+    let image = if orientation == Orientation::default() {
+        image
+    } else {
+        oriented = orientation.apply(image);
+        &oriented
+    };
+    let data =
+        encoder(image, metadata, options).map_err(|err| SaveError::encode(path, format, err))?;
+    atomic_write::write(path, &data, backup).map_err(|err| SaveError::io(path, err))?;
+    info!("image saved at {:?}", path);
+    Ok(())
+}
+
+pub fn encode_jpeg(
+    image: &DynamicImage,
+    metadata: &Metadata,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    let quality = options.jpeg_quality.clamp(1, 100) as i32;
+    let gray = turbojpeg::Subsamp::Gray;
+    let mut jpg = match image {
+        DynamicImage::ImageLuma8(img) => compress_image(img, quality, gray),
+        img if !img.color().has_color() => compress_image(&img.to_luma8(), quality, gray),
+        img => compress_image(&img.to_rgb8(), quality, options.jpeg_subsampling.subsamp()),
+    }
+    .map_err(|err| err.to_string())?;
+    if options.jpeg_progressive {
+        // the compressor only writes baseline, but the coefficients convert losslessly
+        let transform = turbojpeg::Transform {
+            progressive: true,
+            ..turbojpeg::Transform::default()
+        };
+        jpg = turbojpeg::transform(&transform, &jpg).map_err(|err| err.to_string())?;
+    }
+    Ok(metadata.insert_into_jpeg(&jpg))
+}
+
+pub fn encode_png(
+    image: &DynamicImage,
+    metadata: &Metadata,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    let (colour, depth, data) = png_data(image, options.png_depth);
+    let mut png = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+    encoder.set_color(colour);
+    encoder.set_depth(depth);
+    encoder.set_compression(match options.png_compression {
+        PngCompression::Fast => png::Compression::Fast,
+        PngCompression::Default => png::Compression::Default,
+        PngCompression::Best => png::Compression::Best,
+    });
+    let filter = match options.png_filter {
+        PngFilter::None => png::FilterType::NoFilter,
+        PngFilter::Sub | PngFilter::Adaptive => png::FilterType::Sub,
+        PngFilter::Up => png::FilterType::Up,
+        PngFilter::Avg => png::FilterType::Avg,
+        PngFilter::Paeth => png::FilterType::Paeth,
+    };
+    encoder.set_filter(filter);
+    if options.png_filter == PngFilter::Adaptive {
+        encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    }
+    // a gAMA chunk is overridden by an embedded profile, but sRGB may not sit with one
+    match options.png_colour {
+        PngColour::None => (),
+        PngColour::Gamma => encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2)),
+        PngColour::Srgb if metadata.icc.is_some() => (),
+        PngColour::Srgb => encoder.set_srgb(png::SrgbRenderingIntent::Perceptual),
+    }
+
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    metadata
+        .write_png_chunks(&mut writer)
+        .map_err(|err| err.to_string())?;
+    writer
+        .write_image_data(&data)
+        .map_err(|err| err.to_string())?;
+    writer.finish().map_err(|err| err.to_string())?;
+    Ok(png)
+}
+
+pub fn encode_qoi(
+    image: &DynamicImage,
+    _metadata: &Metadata,
+    _options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    // qoi only stores 8 bit RGB and RGBA, telling them apart by the buffer length
+    let data = match image {
+        DynamicImage::ImageRgb8(img) => img.as_raw().clone(),
+        img if img.color().has_alpha() => img.to_rgba8().into_raw(),
+        img => img.to_rgb8().into_raw(),
+    };
+    encode_to_vec(data, image.width(), image.height()).map_err(|err| err.to_string())
+}
+
+pub fn encode_webp(
+    image: &DynamicImage,
+    _metadata: &Metadata,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    let quality = match options.webp_quality {
+        Some(quality) => WebPQuality::lossy(quality),
+        None => WebPQuality::lossless(),
+    };
+    let mut webp = Vec::new();
+    let encoder = WebPEncoder::new_with_quality(&mut webp, quality);
+    match image {
+        DynamicImage::ImageRgb8(img) => {
+            encoder.encode(img, img.width(), img.height(), ColorType::Rgb8)
+        }
+        img if img.color().has_alpha() => {
+            let img = img.to_rgba8();
+            encoder.encode(&img, img.width(), img.height(), ColorType::Rgba8)
+        }
+        img => {
+            let img = img.to_rgb8();
+            encoder.encode(&img, img.width(), img.height(), ColorType::Rgb8)
+        }
+    }
+    .map_err(|err| err.to_string())?;
+    Ok(webp)
+}
+
+pub fn encode_tiff(
+    image: &DynamicImage,
+    _metadata: &Metadata,
+    _options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    // tiff has no grey with alpha, and floating point images are stored at 16 bits
+    let converted;
+    let image = match image.color() {
+        ColorType::L8 | ColorType::Rgb8 | ColorType::Rgba8 => image,
+        ColorType::L16 | ColorType::Rgb16 | ColorType::Rgba16 => image,
+        ColorType::La8 => {
+            converted = DynamicImage::ImageRgba8(image.to_rgba8());
+            &converted
+        }
+        colour if colour.has_alpha() => {
+            converted = DynamicImage::ImageRgba16(image.to_rgba16());
+            &converted
+        }
+        _ => {
+            converted = DynamicImage::ImageRgb16(image.to_rgb16());
+            &converted
+        }
+    };
+    write_with(image, ImageOutputFormat::Tiff)
+}
+
+pub fn encode_bmp(
+    image: &DynamicImage,
+    _metadata: &Metadata,
+    _options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    let colour = image.color();
+    let converted;
+    let image = match (colour.has_color(), colour.has_alpha()) {
+        _ if colour.bytes_per_pixel() == colour.channel_count() => image,
+        (false, false) => {
+            converted = DynamicImage::ImageLuma8(image.to_luma8());
+            &converted
+        }
+        (false, true) => {
+            converted = DynamicImage::ImageLumaA8(image.to_luma_alpha8());
+            &converted
+        }
+        (true, false) => {
+            converted = DynamicImage::ImageRgb8(image.to_rgb8());
+            &converted
+        }
+        (true, true) => {
+            converted = DynamicImage::ImageRgba8(image.to_rgba8());
+            &converted
+        }
+    };
+    write_with(image, ImageOutputFormat::Bmp)
+}
+
+pub fn encode_gif(
+    image: &DynamicImage,
+    _metadata: &Metadata,
+    _options: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    // quantised down to a palette of 256 colours by the encoder
+    write_with(image, ImageOutputFormat::Gif)
+}
+
+/// Encodes `image` with the image crate, which it must already be in a layout `format` takes
+fn write_with(image: &DynamicImage, format: ImageOutputFormat) -> Result<Vec<u8>, String> {
+    let mut data = Cursor::new(Vec::new());
+    image
+        .write_to(&mut data, format)
+        .map_err(|err| err.to_string())?;
+    Ok(data.into_inner())
+}
+
+/// The PNG colour type, bit depth and big endian sample bytes of `image`
+fn png_data(image: &DynamicImage, depth: PngDepth) -> (png::ColorType, png::BitDepth, Vec<u8>) {
+    use png::{BitDepth, ColorType};
+
+    let reduced;
+    let colour = image.color();
+    let image = if depth == PngDepth::Eight && colour.bytes_per_pixel() > colour.channel_count() {
+        reduced = match (colour.has_color(), colour.has_alpha()) {
+            (false, false) => DynamicImage::ImageLuma8(image.to_luma8()),
+            (false, true) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+            (true, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+            (true, true) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        };
+        &reduced
+    } else {
+        image
+    };
+
+    let sixteen = |samples: &[u16]| samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+    match image {
+        DynamicImage::ImageLuma8(img) => (ColorType::Grayscale, BitDepth::Eight, img.to_vec()),
+        DynamicImage::ImageLumaA8(img) => {
+            (ColorType::GrayscaleAlpha, BitDepth::Eight, img.to_vec())
+        }
+        DynamicImage::ImageRgb8(img) => (ColorType::Rgb, BitDepth::Eight, img.to_vec()),
+        DynamicImage::ImageRgba8(img) => (ColorType::Rgba, BitDepth::Eight, img.to_vec()),
+        DynamicImage::ImageLuma16(img) => (ColorType::Grayscale, BitDepth::Sixteen, sixteen(img)),
+        DynamicImage::ImageLumaA16(img) => {
+            (ColorType::GrayscaleAlpha, BitDepth::Sixteen, sixteen(img))
+        }
+        DynamicImage::ImageRgb16(img) => (ColorType::Rgb, BitDepth::Sixteen, sixteen(img)),
+        DynamicImage::ImageRgba16(img) => (ColorType::Rgba, BitDepth::Sixteen, sixteen(img)),
+        // floating point images are stored at the highest depth PNG has
+        img if img.color().has_alpha() => (
+            ColorType::Rgba,
+            BitDepth::Sixteen,
+            sixteen(&img.to_rgba16()),
+        ),
+        img => (ColorType::Rgb, BitDepth::Sixteen, sixteen(&img.to_rgb16())),
+    }
+}
+
+#[cfg(test)]
+mod image_saving_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_save_formats() {
+        assert_eq!(save_format(Path::new("a.JPEG")), Some(Format::Jpeg));
+        assert_eq!(save_format(Path::new("a.gif")), Some(Format::Gif));
+        assert_eq!(save_format(Path::new("a.ico")), None);
+        assert_eq!(
+            with_default_extension(PathBuf::from("dir/photo"), Format::Png),
+            PathBuf::from("dir/photo.png")
+        );
+        assert_eq!(
+            with_default_extension(PathBuf::from("photo.qoi"), Format::Png),
+            PathBuf::from("photo.qoi")
+        );
+    }
+
+    #[test]
+    fn test_save_errors() {
+        let dir = TestDir::new("save");
+        let image = DynamicImage::new_rgba8(2, 2);
+
+        assert!(matches!(
+            save_image(
+                &image,
+                Orientation::default(),
+                &Metadata::default(),
+                &EncodeOptions::default(),
+                &dir.join("image"),
+                false
+            ),
+            Err(SaveError::UnsupportedFormat { .. })
+        ));
+        assert!(matches!(
+            save_image(
+                &image,
+                Orientation::default(),
+                &Metadata::default(),
+                &EncodeOptions::default(),
+                &dir.join("image.ico"),
+                false
+            ),
+            Err(SaveError::UnsupportedFormat { .. })
+        ));
+        assert!(matches!(
+            save_image(
+                &image,
+                Orientation::default(),
+                &Metadata::default(),
+                &EncodeOptions::default(),
+                &dir.join("missing/image.png"),
+                false
+            ),
+            Err(SaveError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_save_applies_orientation() {
+        let dir = TestDir::new("save_orientation");
+        // a 3x2 image whose pixels are numbered left to right, top to bottom
+        let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(3, 2, |x, y| {
+            image::Luma([(x + y * 3) as u8])
+        }));
+
+        // the size of the saved image and where the top left and top right pixels end up
+        for (value, size, top_left, top_right) in [
+            (1, (3, 2), (0, 0), (2, 0)),
+            (2, (3, 2), (2, 0), (0, 0)),
+            (3, (3, 2), (2, 1), (0, 1)),
+            (4, (3, 2), (0, 1), (2, 1)),
+            (5, (2, 3), (0, 0), (0, 2)),
+            (6, (2, 3), (1, 0), (1, 2)),
+            (7, (2, 3), (1, 2), (1, 0)),
+            (8, (2, 3), (0, 2), (0, 0)),
+        ] {
+            let path = dir.join(format!("{}.png", value));
+            save_image(
+                &image,
+                Orientation::from_exif(value),
+                &Metadata::default(),
+                &EncodeOptions::default(),
+                &path,
+                false,
+            )
+            .unwrap();
+            let saved = image::open(&path).unwrap().into_luma8();
+            assert_eq!(saved.dimensions(), size, "orientation {}", value);
+            assert_eq!(
+                saved.get_pixel(top_left.0, top_left.1).0,
+                [0],
+                "orientation {}",
+                value
+            );
+            assert_eq!(
+                saved.get_pixel(top_right.0, top_right.1).0,
+                [2],
+                "orientation {}",
+                value
+            );
+        }
+
+        // rotating by hand gives the same result as the matching EXIF orientation
+        let path = dir.join("clockwise.png");
+        let clockwise = Orientation::default().clockwise();
+        save_image(
+            &image,
+            clockwise,
+            &Metadata::default(),
+            &EncodeOptions::default(),
+            &path,
+            false,
+        )
+        .unwrap();
+        let expected = Orientation::from_exif(6).apply(&image);
+        assert_eq!(image::open(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_save_keeps_layout_and_depth() {
+        let dir = TestDir::new("save_layout");
+
+        for (source, file, colour) in [
+            ("png_rgb16.png", "rgb16.png", image::ColorType::Rgb16),
+            (
+                "png_gray_alpha8.png",
+                "gray_alpha.png",
+                image::ColorType::La8,
+            ),
+            ("dice.jpg", "rgb.qoi", image::ColorType::Rgb8),
+            ("testcard_rgba.png", "rgba.qoi", image::ColorType::Rgba8),
+            ("png_gray8.png", "gray.jpg", image::ColorType::L8),
+            ("testcard_rgba.png", "rgb.jpeg", image::ColorType::Rgb8),
+        ] {
+            let image = image::open(format!("./test_images/{}", source)).unwrap();
+            save_image(
+                &image,
+                Orientation::default(),
+                &Metadata::default(),
+                &EncodeOptions::default(),
+                &dir.join(file),
+                false,
+            )
+            .unwrap();
+            let saved = image::open(dir.join(file)).unwrap();
+            assert_eq!(saved.color(), colour, "{}", file);
+            assert_eq!(saved.width(), image.width());
+            if file.ends_with(".png") {
+                assert_eq!(saved, image, "{}", file);
             }
-            Some("qoi") => {
-                let encoded = encode_to_vec(data, width, height).unwrap();
-                File::create(path).unwrap().write_all(&encoded).unwrap();
-                info!("image saved at {:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_save_other_formats() {
+        let dir = TestDir::new("save_formats");
+        let lossless = EncodeOptions {
+            webp_quality: None,
+            ..EncodeOptions::default()
+        };
+
+        for (source, file, colour) in [
+            ("testcard_rgba.png", "rgba.webp", image::ColorType::Rgba8),
+            // lossless webp is always read back with alpha
+            ("dice.jpg", "rgb.webp", image::ColorType::Rgba8),
+            ("png_rgb16.png", "rgb16.tif", image::ColorType::Rgb16),
+            (
+                "png_gray_alpha8.png",
+                "gray_alpha.tif",
+                image::ColorType::Rgba8,
+            ),
+            ("dice.jpg", "rgb.bmp", image::ColorType::Rgb8),
+            ("png_rgba16.png", "rgba.bmp", image::ColorType::Rgba8),
+            ("qoi_logo.png", "logo.gif", image::ColorType::Rgba8),
+        ] {
+            let image = image::open(format!("./test_images/{}", source)).unwrap();
+            save_image(
+                &image,
+                Orientation::default(),
+                &Metadata::default(),
+                &lossless,
+                &dir.join(file),
+                false,
+            )
+            .unwrap();
+            let saved = image::open(dir.join(file)).unwrap();
+            assert_eq!(saved.color(), colour, "{}", file);
+            assert_eq!(
+                (saved.width(), saved.height()),
+                (image.width(), image.height()),
+                "{}",
+                file
+            );
+            if !file.ends_with(".gif") {
+                // lossless webp does not keep the colour of invisible pixels
+                let visible = |pixel: &image::Rgba<u8>| match pixel.0 {
+                    [_, _, _, 0] => [0; 4],
+                    channels => channels,
+                };
+                let saved: Vec<_> = saved.to_rgba8().pixels().map(visible).collect();
+                let expected: Vec<_> = image.to_rgba8().pixels().map(visible).collect();
+                assert!(saved == expected, "{}", file);
             }
-            _ => (),
-        },
-        None => todo!(),
-    };
+        }
+    }
+
+    #[test]
+    fn test_save_keeps_metadata() {
+        let dir = TestDir::new("save_metadata");
+        let source = Path::new("./test_images/metadata.jpg");
+        let image = image::open(source).unwrap();
+        let metadata = Metadata::read(source);
+
+        for file in ["copy.jpg", "copy.png", "copy.qoi"] {
+            save_image(
+                &image,
+                Orientation::default(),
+                &metadata,
+                &EncodeOptions::default(),
+                &dir.join(file),
+                false,
+            )
+            .unwrap();
+        }
+        assert_eq!(Metadata::read(&dir.join("copy.jpg")), metadata);
+        assert_eq!(Metadata::read(&dir.join("copy.png")), metadata);
+        // qoi has nowhere to put it
+        assert_eq!(Metadata::read(&dir.join("copy.qoi")), Metadata::default());
+    }
+
+    #[test]
+    fn test_encode_options() {
+        let dir = TestDir::new("save_options");
+        let image = image::open("./test_images/png_rgb16.png").unwrap();
+        let save = |file: &str, options: EncodeOptions| {
+            let path = dir.join(file);
+            save_image(
+                &image,
+                Orientation::default(),
+                &Metadata::default(),
+                &options,
+                &path,
+                false,
+            )
+            .unwrap();
+            path
+        };
+
+        let best = save("best.jpg", EncodeOptions::default());
+        let low = EncodeOptions {
+            jpeg_quality: 10,
+            ..EncodeOptions::default()
+        };
+        let low = save("low.jpg", low);
+        assert!(fs::metadata(low).unwrap().len() < fs::metadata(best).unwrap().len());
+
+        let eight = EncodeOptions {
+            png_depth: PngDepth::Eight,
+            png_filter: PngFilter::Paeth,
+            png_colour: PngColour::Srgb,
+            ..EncodeOptions::default()
+        };
+        let eight = save("eight.png", eight);
+        let bytes = fs::read(&eight).unwrap();
+        assert!(bytes.windows(4).any(|chunk| chunk == b"sRGB"));
+        let saved = image::open(eight).unwrap();
+        assert_eq!(saved.color(), image::ColorType::Rgb8);
+        assert_eq!(saved, DynamicImage::ImageRgb8(image.to_rgb8()));
+
+        let plain = fs::read(save("default.png", EncodeOptions::default())).unwrap();
+        assert!(plain.windows(4).any(|chunk| chunk == b"gAMA"));
+        assert!(!plain.windows(4).any(|chunk| chunk == b"sRGB"));
+    }
 }
@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use crate::sort::{self, SortMode};
+
+/// The images being browsed, in browsing order, and which of them is shown
+///
+/// Built once when a directory is opened and then kept up to date, so moving between images
+/// never reads the directory again.
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    files: Vec<PathBuf>,
+    /// `None` while the image shown is not one of `files`
+    position: Option<usize>,
+    /// Whether `files` are in a random order, which new files are added to the end of
+    shuffled: bool,
+}
+
+impl FileIndex {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        Self {
+            files,
+            position: None,
+            shuffled: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn first(&self) -> Option<&Path> {
+        self.files.first().map(PathBuf::as_path)
+    }
+
+    pub fn position(&self) -> Option<usize> {
+        self.position
+    }
+
+    /// Makes `path` the current image, returning whether it is in the index
+    pub fn select(&mut self, path: &Path) -> bool {
+        self.position = self.files.iter().position(|f| f == path);
+        self.position.is_some()
+    }
+
+    /// Moves to the image at `index`, if there is one
+    pub fn go_to(&mut self, index: usize) -> Option<&Path> {
+        if index >= self.files.len() {
+            return None;
+        }
+        self.position = Some(index);
+        Some(&self.files[index])
+    }
+
+    /// Moves `offset` images forward or back, either stopping short of the ends or with `wrap`
+    /// carrying on from the other end
+    pub fn step(&mut self, offset: isize, wrap: bool) -> Option<&Path> {
+        let position = self.position?;
+        let index = if wrap {
+            (position as isize + offset).rem_euclid(self.files.len() as isize) as usize
+        } else {
+            position.checked_add_signed(offset)?
+        };
+        self.go_to(index)
+    }
+
+    /// Up to `distance` images either side of the current one, nearest first
+    pub fn neighbours(&self, distance: usize) -> Vec<PathBuf> {
+        let current = match self.position {
+            Some(current) => current,
+            None => return Vec::new(),
+        };
+        let mut neighbours = Vec::with_capacity(distance * 2);
+        for offset in 1..=distance {
+            if let Some(next) = self.files.get(current + offset) {
+                neighbours.push(next.clone());
+            }
+            if let Some(prev) = current.checked_sub(offset).and_then(|i| self.files.get(i)) {
+                neighbours.push(prev.clone());
+            }
+        }
+        neighbours
+    }
+
+    /// Adds `path` where `mode` puts it, keeping the current image selected
+    pub fn insert(&mut self, path: PathBuf, mode: SortMode, reverse: bool) {
+        if self.files.contains(&path) {
+            return;
+        }
+        let index = if self.shuffled {
+            self.files.len()
+        } else {
+            self.files
+                .partition_point(|f| sort::compare(f, &path, mode, reverse).is_lt())
+        };
+        self.files.insert(index, path);
+        if let Some(position) = self.position.as_mut() {
+            if *position >= index {
+                *position += 1;
+            }
+        }
+    }
+
+    /// Takes `path` out of the index, returning whether it was there
+    ///
+    /// Removing the current image leaves nothing selected.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        let index = match self.files.iter().position(|f| f == path) {
+            Some(index) => index,
+            None => return false,
+        };
+        self.files.remove(index);
+        self.position = match self.position {
+            Some(position) if position == index => None,
+            Some(position) if position > index => Some(position - 1),
+            position => position,
+        };
+        true
+    }
+
+    /// Puts the images in a new order, keeping the current image selected
+    pub fn sort(&mut self, mode: SortMode, reverse: bool) {
+        let current = self.position.map(|i| self.files[i].clone());
+        sort::sort_files(&mut self.files, mode, reverse);
+        self.shuffled = false;
+        if let Some(current) = current {
+            self.select(&current);
+        }
+    }
+
+    /// Puts the images in a random order given by `seed`, keeping the current image selected
+    pub fn shuffle(&mut self, seed: u64) {
+        let current = self.position.map(|i| self.files[i].clone());
+        sort::shuffle(&mut self.files, seed);
+        self.shuffled = true;
+        if let Some(current) = current {
+            self.select(&current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod index_tests {
+    use super::*;
+
+    fn index(names: &[&str]) -> FileIndex {
+        FileIndex::new(names.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut index = index(&["a.png", "b.png", "c.png"]);
+        assert_eq!(index.step(1, true), None);
+        assert!(index.select(Path::new("b.png")));
+        assert_eq!(index.step(1, false), Some(Path::new("c.png")));
+        assert_eq!(index.step(1, false), None);
+        assert_eq!(index.position(), Some(2));
+        assert_eq!(index.step(1, true), Some(Path::new("a.png")));
+        assert_eq!(index.step(-1, false), None);
+        assert_eq!(index.step(-1, true), Some(Path::new("c.png")));
+        assert_eq!(index.go_to(0), Some(Path::new("a.png")));
+        assert_eq!(index.go_to(3), None);
+        assert_eq!(index.neighbours(1), vec![PathBuf::from("b.png")]);
+        assert!(!index.select(Path::new("d.png")));
+        assert!(index.neighbours(1).is_empty());
+    }
+
+    #[test]
+    fn test_updates_keep_position() {
+        let mut index = index(&["IMG_1.jpg", "IMG_3.jpg", "IMG_10.jpg"]);
+        index.select(Path::new("IMG_3.jpg"));
+
+        index.insert(PathBuf::from("IMG_2.jpg"), SortMode::Name, false);
+        assert_eq!(index.position(), Some(2));
+        index.insert(PathBuf::from("IMG_20.jpg"), SortMode::Name, false);
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.go_to(4), Some(Path::new("IMG_20.jpg")));
+
+        index.select(Path::new("IMG_3.jpg"));
+        assert!(index.remove(Path::new("IMG_1.jpg")));
+        assert!(!index.remove(Path::new("IMG_1.jpg")));
+        assert_eq!(index.position(), Some(1));
+
+        index.sort(SortMode::Name, true);
+        assert_eq!(index.first(), Some(Path::new("IMG_20.jpg")));
+        assert_eq!(index.position(), Some(2));
+        index.insert(PathBuf::from("IMG_4.jpg"), SortMode::Name, true);
+        assert_eq!(index.position(), Some(3));
+        assert_eq!(index.step(-1, false), Some(Path::new("IMG_4.jpg")));
+
+        assert!(index.remove(Path::new("IMG_4.jpg")));
+        assert_eq!(index.position(), None);
+
+        index.select(Path::new("IMG_3.jpg"));
+        index.shuffle(7);
+        assert_eq!(
+            index.files[index.position().unwrap()],
+            Path::new("IMG_3.jpg")
+        );
+        index.insert(PathBuf::from("IMG_0.jpg"), SortMode::Name, false);
+        assert_eq!(index.go_to(4), Some(Path::new("IMG_0.jpg")));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use turbojpeg::{Transform, TransformOp};
+
+use crate::{
+    atomic_write,
+    format::Format,
+    metadata,
+    rotation::{Orientation, Rotation},
+};
+
+#[derive(Debug)]
+pub enum RotateError {
+    NotJpeg {
+        path: PathBuf,
+    },
+    /// The image does not fill its last row or column of MCUs and trimming was not allowed
+    Imperfect {
+        path: PathBuf,
+        mcu: (usize, usize),
+    },
+    /// turbojpeg could not read or transform the file
+    Transform {
+        path: PathBuf,
+        reason: String,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl RotateError {
+    pub fn path(&self) -> &Path {
+        match self {
+            RotateError::NotJpeg { path }
+            | RotateError::Imperfect { path, .. }
+            | RotateError::Transform { path, .. }
+            | RotateError::Io { path, .. } => path,
+        }
+    }
+
+    /// Short, user facing description of the failure
+    pub fn reason(&self) -> String {
+        match self {
+            RotateError::NotJpeg { .. } => String::from("only JPEGs can be rotated losslessly"),
+            RotateError::Imperfect { mcu, .. } => format!(
+                "size is not a multiple of {}x{}, trim the edges to rotate",
+                mcu.0, mcu.1
+            ),
+            RotateError::Transform { reason, .. } => format!("transform failed: {}", reason),
+            RotateError::Io { source, .. } => source.to_string(),
+        }
+    }
+
+    fn transform(path: &Path, err: turbojpeg::Error) -> Self {
+        RotateError::Transform {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        }
+    }
+
+    fn io(path: &Path, source: io::Error) -> Self {
+        RotateError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for RotateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RotateError: {:?}: {}", self.path(), self.reason())
+    }
+}
+
+impl std::error::Error for RotateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RotateError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The lossless transform that stores a JPEG the way `orientation` shows it
+pub fn transform_op(orientation: Orientation) -> TransformOp {
+    match (orientation.rotation, orientation.mirrored) {
+        (Rotation::Up, false) => TransformOp::None,
+        (Rotation::Up, true) => TransformOp::Hflip,
+        (Rotation::Right, false) => TransformOp::Rot90,
+        (Rotation::Right, true) => TransformOp::Transverse,
+        (Rotation::Down, false) => TransformOp::Rot180,
+        (Rotation::Down, true) => TransformOp::Vflip,
+        (Rotation::Left, false) => TransformOp::Rot270,
+        (Rotation::Left, true) => TransformOp::Transpose,
+    }
+}
+
+/// Whether `op` moves only whole MCUs of an image of `size`
+///
+/// A partial MCU can only stay on the right or bottom edge, so an op that would move one
+/// anywhere else has to either trim it or fail.
+fn is_perfect(op: TransformOp, size: (usize, usize), mcu: (usize, usize)) -> bool {
+    let whole_columns = size.0.is_multiple_of(mcu.0);
+    let whole_rows = size.1.is_multiple_of(mcu.1);
+    match op {
+        TransformOp::None | TransformOp::Transpose => true,
+        TransformOp::Hflip | TransformOp::Rot270 => whole_columns,
+        TransformOp::Vflip | TransformOp::Rot90 => whole_rows,
+        TransformOp::Transverse | TransformOp::Rot180 => whole_columns && whole_rows,
+    }
+}
+
+/// Rewrites the JPEG at `path` so it is stored the way `orientation` shows it
+///
+/// The DCT blocks are rearranged rather than decoded, so no quality is lost. Other metadata
+/// is kept and the EXIF orientation is reset to upright. An image that does not fill its
+/// edge MCUs cannot be turned exactly; with `trim` those edges are cut off, otherwise
+/// [`RotateError::Imperfect`] is returned and the file is left alone. The file is replaced
+/// atomically, keeping the original as a `.bak` with `backup`.
+pub fn rotate_in_place(
+    path: &Path,
+    orientation: Orientation,
+    trim: bool,
+    backup: bool,
+) -> Result<(), RotateError> {
+    let data = fs::read(path).map_err(|err| RotateError::io(path, err))?;
+    if Format::sniff(&data) != Some(Format::Jpeg) {
+        return Err(RotateError::NotJpeg {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let op = transform_op(orientation);
+    let header = turbojpeg::read_header(&data).map_err(|err| RotateError::transform(path, err))?;
+    let mcu = (header.subsamp.mcu_width(), header.subsamp.mcu_height());
+    if !trim && !is_perfect(op, (header.width, header.height), mcu) {
+        return Err(RotateError::Imperfect {
+            path: path.to_path_buf(),
+            mcu,
+        });
+    }
+
+    let transform = Transform {
+        op,
+        perfect: !trim,
+        trim,
+        ..Transform::default()
+    };
+    let mut rotated = turbojpeg::transform(&transform, &data)
+        .map_err(|err| RotateError::transform(path, err))?
+        .to_vec();
+    if !metadata::reset_jpeg_orientation(&mut rotated) {
+        debug!("jpeg: {:?} has no EXIF orientation to reset", path);
+    }
+
+    atomic_write::write(path, &rotated, backup).map_err(|err| RotateError::io(path, err))?;
+    info!("rotated {:?} losslessly with {:?}", path, op);
+    Ok(())
+}
+
+#[cfg(test)]
+mod jpeg_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use exif::Tag;
+
+    fn temp_copy(name: &str) -> (TestDir, PathBuf) {
+        let dir = TestDir::new("jpeg");
+        let path = dir.join(name);
+        fs::copy(Path::new("./test_images").join(name), &path).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_transform_ops_match_orientations() {
+        let image = image::open("./test_images/exif_orientation_6.jpg").unwrap();
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value);
+            let expected = orientation.apply(&image).to_rgb8();
+            // the same turn done the way turbojpeg describes it
+            let turned = match transform_op(orientation) {
+                TransformOp::None => image.clone(),
+                TransformOp::Hflip => image.fliph(),
+                TransformOp::Vflip => image.flipv(),
+                TransformOp::Transpose => image.rotate90().fliph(),
+                TransformOp::Transverse => image.rotate270().fliph(),
+                TransformOp::Rot90 => image.rotate90(),
+                TransformOp::Rot180 => image.rotate180(),
+                TransformOp::Rot270 => image.rotate270(),
+            };
+            assert_eq!(turned.to_rgb8(), expected, "orientation {}", value);
+        }
+    }
+
+    #[test]
+    fn test_is_perfect() {
+        assert!(is_perfect(TransformOp::Rot90, (44, 32), (16, 16)));
+        assert!(!is_perfect(TransformOp::Rot90, (32, 44), (16, 16)));
+        assert!(is_perfect(TransformOp::Transpose, (44, 44), (16, 16)));
+        assert!(!is_perfect(TransformOp::Rot180, (32, 44), (16, 16)));
+        assert!(is_perfect(TransformOp::Hflip, (32, 44), (16, 16)));
+    }
+
+    #[test]
+    fn test_rotate_in_place() {
+        let (_dir, path) = temp_copy("exif_orientation_6.jpg");
+        let orientation = Orientation::read(&path).unwrap();
+        assert_eq!(orientation, Rotation::Right.into());
+
+        let original = fs::read(&path).unwrap();
+        rotate_in_place(&path, orientation, false, true).unwrap();
+        assert_eq!(
+            fs::read(atomic_write::backup_path(&path)).unwrap(),
+            original
+        );
+        assert_eq!(Orientation::read(&path).unwrap(), Orientation::default());
+        let rotated = image::open(&path).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (32, 48));
+        // the top left quadrant is red, and ends up top right
+        let pixel = rotated.to_rgb8().get_pixel(28, 4).0;
+        assert!(
+            pixel[0] > 200 && pixel[1] < 50 && pixel[2] < 50,
+            "{:?}",
+            pixel
+        );
+
+        // the rest of the EXIF is kept
+        let file = fs::File::open(&path).unwrap();
+        let exif = exif::Reader::new()
+            .read_from_container(&mut io::BufReader::new(file))
+            .unwrap();
+        let make = exif.get_field(Tag::Make, exif::In::PRIMARY).unwrap();
+        assert_eq!(make.display_value().to_string(), "\"femto\"");
+    }
+
+    #[test]
+    fn test_uneven_edges_need_trimming() {
+        let (_dir, path) = temp_copy("exif_orientation_6_uneven.jpg");
+        let before = fs::read(&path).unwrap();
+        let orientation = Orientation::read(&path).unwrap();
+
+        let err = rotate_in_place(&path, orientation, false, false).unwrap_err();
+        assert!(matches!(err, RotateError::Imperfect { .. }), "{}", err);
+        assert_eq!(fs::read(&path).unwrap(), before);
+
+        rotate_in_place(&path, orientation, true, false).unwrap();
+        let rotated = image::open(&path).unwrap();
+        assert!(rotated.width() < 28 && rotated.height() <= 44);
+        assert_eq!(Orientation::read(&path).unwrap(), Orientation::default());
+    }
+
+    #[test]
+    fn test_only_jpegs() {
+        let err = rotate_in_place(
+            Path::new("./test_images/dice.png"),
+            Rotation::Right.into(),
+            true,
+            false,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RotateError::NotJpeg { .. }));
+    }
+}
@@ -5,25 +5,97 @@ extern crate glium;
 extern crate exif;
 extern crate image;
 
+mod animation;
+mod atomic_write;
+mod cache;
+mod cli;
+mod config;
+mod convert;
+mod format;
+mod geometry;
 mod image_loading;
 mod image_saving;
+mod index;
+mod jpeg;
+mod metadata;
 mod rotation;
+mod slideshow;
+mod sort;
+mod source;
 mod state;
-use image_saving::save_image;
+#[cfg(test)]
+mod test_dir;
+mod view;
+mod watch;
+use animation::Playback;
+use cache::Preloader;
+use cli::Command;
+use config::Config;
+use format::Format;
+use geometry::Geometry;
+use image_loading::{texture_data, Decoded, LoadError};
+use image_saving::{save_image, SaveError};
+use jpeg::RotateError;
+use metadata::Metadata;
 use rfd::FileDialog;
+use rotation::Orientation;
+use slideshow::{Fade, Slideshow};
 use state::State;
+use watch::FileChange;
 
 use core::fmt;
 use glium::{
     glutin::{
+        dpi::{PhysicalPosition, PhysicalSize},
         event::{ElementState, ModifiersState, VirtualKeyCode},
-        window::Icon,
+        event_loop::EventLoopProxy,
+        monitor::MonitorHandle,
+        window::{Fullscreen, Icon, Window},
     },
     texture::SrgbTexture2d,
-    Blend, Display, DrawParameters,
+    Blend, BlendingFunction, Display, DrawParameters, LinearBlendingFactor,
 };
 use log::{debug, info, trace, warn, LevelFilter};
-use std::{env, ffi::OsString, path::Path, thread};
+use std::{
+    env, mem,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the mouse has to be still before the cursor is hidden while fullscreen
+const CURSOR_HIDE_DELAY: Duration = Duration::from_secs(2);
+/// The longest gap between the clicks of a double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// Blending for colours already multiplied by their alpha, as the cross-fade draws them
+const PREMULTIPLIED_BLENDING: Blend = Blend {
+    color: BlendingFunction::Addition {
+        source: LinearBlendingFactor::One,
+        destination: LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    alpha: BlendingFunction::Addition {
+        source: LinearBlendingFactor::One,
+        destination: LinearBlendingFactor::OneMinusSourceAlpha,
+    },
+    constant_value: (0.0, 0.0, 0.0, 0.0),
+};
+/// The transform from the unit quad to the whole window
+const FULL_WINDOW: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Events sent to the event loop from other threads
+#[derive(Debug)]
+pub enum UserEvent {
+    /// A background decode finished, successfully or not
+    Preloaded(PathBuf),
+    /// A background save finished
+    Saved(Result<PathBuf, SaveError>),
+    /// A lossless rotation of a file finished
+    Rotated(Result<PathBuf, RotateError>),
+    /// A file in the browsed directory changed
+    FileChanged(FileChange),
+}
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -33,20 +105,240 @@ struct Vertex {
 
 implement_vertex!(Vertex, position, tex_coords);
 
+/// The textures for the current image, one per frame when it is animated
+struct LoadedTexture {
+    /// The decoded pixels the textures were made from, kept for saving
+    decoded: Arc<Decoded>,
+    frames: Vec<SrgbTexture2d>,
+    playback: Option<Playback>,
+    size: (u32, u32),
+    /// The error that caused the placeholder image to be shown instead
+    error: Option<LoadError>,
+}
+
+impl LoadedTexture {
+    fn current(&self) -> &SrgbTexture2d {
+        match &self.playback {
+            Some(playback) => &self.frames[playback.current()],
+            None => &self.frames[0],
+        }
+    }
+
+    fn current_index(&self) -> usize {
+        self.playback.as_ref().map_or(0, Playback::current)
+    }
+}
+
+/// The image being faded out as the slideshow moves on
+struct Outgoing {
+    loaded: LoadedTexture,
+    /// Where it was drawn, as the view has since changed for the new image
+    p_view: [[f32; 3]; 3],
+    fade: Fade,
+}
+
+/// Uploads a decoded image into textures, one per frame
+fn upload_texture(
+    display: &Display,
+    decoded: Arc<Decoded>,
+) -> Result<LoadedTexture, Box<dyn std::error::Error>> {
+    let (frames, playback) = match decoded.as_ref() {
+        Decoded::Still(image) => (
+            vec![SrgbTexture2d::new(display, texture_data(image))?],
+            None,
+        ),
+        Decoded::Animated(frames) => {
+            let mut delays = Vec::with_capacity(frames.len());
+            let mut textures = Vec::with_capacity(frames.len());
+            for frame in frames {
+                delays.push(frame.delay);
+                textures.push(SrgbTexture2d::new(display, texture_data(&frame.image))?);
+            }
+            info!("{} animation frames loaded", textures.len());
+            (textures, Some(Playback::new(delays, Instant::now())))
+        }
+    };
+    let loaded = LoadedTexture {
+        size: decoded.size(),
+        decoded,
+        frames,
+        playback,
+        error: None,
+    };
+    info!("texture loaded");
+    Ok(loaded)
+}
+
+/// Decodes and loads the current image on this thread, falling back to the placeholder image
 fn load_texture(
     display: &Display,
     state: &State,
-) -> Result<(SrgbTexture2d, (u32, u32)), Box<dyn std::error::Error>> {
+) -> Result<LoadedTexture, Box<dyn std::error::Error>> {
     info!("loading texture");
-    let image = image_loading::load_image(Path::new(&state.image_uri))?;
-    debug!("got image");
-    let image_size = (image.width, image.height);
-    debug!("got image size");
-    // println!("image loaded: {:?}", start.elapsed());
-    let texture = glium::texture::SrgbTexture2d::new(display, image)?;
-    // println!("texture loaded: {:?}", start.elapsed());
-    info!("texture loaded");
-    Ok((texture, image_size))
+    match image_loading::decode(Path::new(&state.image_uri)) {
+        Ok(decoded) => upload_texture(display, Arc::new(decoded)),
+        Err(err) => {
+            warn!("{}", err);
+            let (image, err) = image_loading::load_fallback(err)?;
+            let mut loaded = upload_texture(display, Arc::new(Decoded::Still(image)))?;
+            loaded.error = Some(err);
+            Ok(loaded)
+        }
+    }
+}
+
+fn image_name(state: &State) -> &str {
+    Path::new(&state.image_uri)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+}
+
+/// Whether `a` and `b` name the same file, however they are written
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Writes the image being shown to `file` in the background, as it is shown
+fn start_save(
+    file: PathBuf,
+    state: &State,
+    loaded: &LoadedTexture,
+    proxy: &EventLoopProxy<UserEvent>,
+) {
+    info!("Saving to {:?}", file);
+
+    let decoded = loaded.decoded.clone();
+    let frame = loaded.current_index();
+    let orientation = state.orientation;
+    let source = PathBuf::from(&state.image_uri);
+    let strip = state.strip;
+    let encode = state.encode;
+    let backup = state.backup;
+    let proxy = proxy.clone();
+    thread::spawn(move || {
+        let mut metadata = Metadata::read(&source);
+        metadata.strip(strip);
+        metadata.reset_orientation();
+        let result = save_image(
+            decoded.frame(frame),
+            orientation,
+            &metadata,
+            &encode,
+            &file,
+            backup,
+        )
+        .map(|()| file);
+        // the event loop is gone when the window is closing
+        let _ = proxy.send_event(UserEvent::Saved(result));
+    });
+}
+
+/// The digit typed by a number row or numpad key
+fn digit(key: VirtualKeyCode) -> Option<char> {
+    let digit = match key {
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => '0',
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => '1',
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => '2',
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => '3',
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => '4',
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => '5',
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => '6',
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => '7',
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => '8',
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => '9',
+        _ => return None,
+    };
+    Some(digit)
+}
+
+/// Keys that change how the slideshow is shown rather than taking over browsing
+fn leaves_slideshow_running(key: VirtualKeyCode) -> bool {
+    matches!(
+        key,
+        VirtualKeyCode::F5
+            | VirtualKeyCode::F11
+            | VirtualKeyCode::F
+            | VirtualKeyCode::Escape
+            | VirtualKeyCode::LShift
+            | VirtualKeyCode::RShift
+            | VirtualKeyCode::LControl
+            | VirtualKeyCode::RControl
+            | VirtualKeyCode::LAlt
+            | VirtualKeyCode::RAlt
+            | VirtualKeyCode::LWin
+            | VirtualKeyCode::RWin
+    )
+}
+
+/// Goes fullscreen on the monitor the window is on, or back to a window
+///
+/// Returns when to hide the cursor, which is only hidden while fullscreen.
+fn set_fullscreen(window: &Window, fullscreen: bool) -> Option<Instant> {
+    let mode = fullscreen.then(|| Fullscreen::Borderless(window.current_monitor()));
+    window.set_fullscreen(mode);
+    window.set_cursor_visible(true);
+    fullscreen.then(|| Instant::now() + CURSOR_HIDE_DELAY)
+}
+
+/// The window's size and position, unless it is fullscreen or minimised
+fn window_geometry(window: &Window) -> Option<Geometry> {
+    let size = window.inner_size();
+    if window.fullscreen().is_some() || size.width == 0 || size.height == 0 {
+        return None;
+    }
+    // not every platform lets a window know where it is
+    let position = window.outer_position().map_or((0, 0), |p| (p.x, p.y));
+    Some(Geometry {
+        position,
+        size: (size.width, size.height),
+    })
+}
+
+fn on_monitor(monitor: &MonitorHandle, position: (i32, i32)) -> bool {
+    let (start, size) = (monitor.position(), monitor.size());
+    (start.x..start.x + size.width as i32).contains(&position.0)
+        && (start.y..start.y + size.height as i32).contains(&position.1)
+}
+
+fn slideshow_status(slideshow: &Slideshow) -> String {
+    if slideshow.is_running() {
+        format!("slideshow, every {}s", slideshow.interval().as_secs_f32())
+    } else {
+        String::from("slideshow paused")
+    }
+}
+
+fn window_title(state: &State, loaded: &LoadedTexture) -> String {
+    let name = state.title_name();
+    let title = match (&loaded.error, &loaded.playback) {
+        (Some(err), _) => format!("FemtoPhotos: {} (could not open: {})", name, err.reason()),
+        (None, Some(playback)) if playback.is_paused() => format!(
+            "FemtoPhotos: {} [frame {}/{}, paused]",
+            name,
+            playback.current() + 1,
+            playback.frame_count()
+        ),
+        (None, _) => format!("FemtoPhotos: {}", name),
+    };
+    let order = state.order_text();
+    let title = match state.position_text() {
+        Some(position) => format!("{} [{}, {}]", title, position, order),
+        None => format!("{} [{}]", title, order),
+    };
+    match (&state.overwrite, &state.jump, &state.status) {
+        (Some(file), _, _) => format!(
+            "{} (overwrite {}? Y or N)",
+            title,
+            file.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        (None, Some(jump), _) => format!("{} (go to: {}_)", title, jump),
+        (None, None, Some(status)) => format!("{} ({})", title, status),
+        (None, None, None) => title,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -60,7 +352,7 @@ impl fmt::Display for LogFileError {
     }
 }
 
-fn attempt_log_file() -> Result<(), LogFileError> {
+fn attempt_log_file(level: LevelFilter) -> Result<(), LogFileError> {
     let current_exe = match env::current_exe() {
         Ok(exe) => exe,
         Err(err) => {
@@ -85,7 +377,7 @@ fn attempt_log_file() -> Result<(), LogFileError> {
             })
         }
     };
-    match simple_logging::log_to_file(format!("{}/latest.log", dir_str), LevelFilter::Trace) {
+    match simple_logging::log_to_file(format!("{}/latest.log", dir_str), level) {
         Ok(()) => Ok(()),
         Err(err) => Err(LogFileError {
             err_str: err.to_string(),
@@ -93,32 +385,112 @@ fn attempt_log_file() -> Result<(), LogFileError> {
     }
 }
 
+/// Reads the config next to the executable and applies the settings given on the command line
+fn load_config(settings: &[(String, String)]) -> Config {
+    let mut config = match Config::path().map(|path| Config::load_or_create(&path)) {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            warn!("{}", err);
+            eprintln!("femtophotos: {}, using the default settings", err.reason());
+            Config::default()
+        }
+        None => Config::default(),
+    };
+    for (key, value) in settings {
+        if let Err(err) = config.set(key, value) {
+            warn!("--set {}={}: {:?}", key, value, err);
+        }
+    }
+    config
+}
+
+/// Attaches to the console femtophotos was started from, if it was started from one
+///
+/// As a GUI program it starts without a console, so anything printed for the command line would
+/// otherwise go nowhere.
+#[cfg(windows)]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // SAFETY: takes no pointers, and failing only means there is no console to attach to
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
 fn main() {
-    match attempt_log_file() {
+    // opened from the desktop there are no arguments, and nothing to print
+    if env::args_os().len() > 1 {
+        attach_console();
+    }
+    let options = match cli::parse(env::args_os().skip(1)) {
+        Ok(Command::View(options)) => options,
+        Ok(Command::Convert(options)) => {
+            match attempt_log_file(options.log_level) {
+                Ok(()) => info!("Logging to latest.log"),
+                Err(err) => warn!("Logging to stdout: {:?}", err),
+            }
+            let config = load_config(&options.settings);
+            let succeeded = convert::run(&options, &config);
+            process::exit(if succeeded { 0 } else { 1 });
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("femtophotos {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("femtophotos: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    match attempt_log_file(options.log_level) {
         Ok(()) => info!("Logging to latest.log"),
         Err(err) => warn!("Logging to stdout: {:?}", err),
     }
 
     info!("dir: {:?}", env::current_dir());
     info!("exe: {:?}", env::current_exe());
-
-    let args: Vec<OsString> = env::args_os().collect();
-    info!("{:?}", &args);
+    info!("{:?}", options);
 
     use glium::glutin;
     use glium::Surface;
 
-    let event_loop = glutin::event_loop::EventLoop::new();
+    let event_loop = glutin::event_loop::EventLoopBuilder::<UserEvent>::with_user_event().build();
     let icon = match image_loading::icon() {
         Ok((data, (width, height))) => Some(Icon::from_rgba(data, width, height).unwrap()),
         Err(_) => None,
     };
-    let wb = glutin::window::WindowBuilder::new()
+    let mut wb = glutin::window::WindowBuilder::new()
         .with_title("FemtoPhotos: ")
         .with_transparent(true)
-        .with_window_icon(icon);
+        .with_window_icon(icon)
+        .with_fullscreen(options.fullscreen.then_some(Fullscreen::Borderless(None)));
+    let geometry_path = Geometry::path();
+    if let Some(saved) = geometry_path.as_deref().and_then(Geometry::load) {
+        wb = wb.with_inner_size(PhysicalSize::new(saved.size.0, saved.size.1));
+        // a window last seen on a monitor that has since gone is left for the system to place
+        if event_loop
+            .available_monitors()
+            .any(|monitor| on_monitor(&monitor, saved.position))
+        {
+            wb = wb.with_position(PhysicalPosition::new(saved.position.0, saved.position.1));
+        }
+    }
     let cb = glutin::ContextBuilder::new();
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
+    // the size and position to restore next time, kept while fullscreen
+    let mut windowed = window_geometry(display.gl_window().window());
 
     let vertex1 = Vertex {
         position: [-1.0, -1.0],
@@ -147,12 +519,12 @@ fn main() {
     in vec2 tex_coords;
     out vec2 v_tex_coords;
 
-    uniform mat2 p_rot;
+    uniform mat3 p_view;
 
     void main() {
         v_tex_coords = tex_coords;
 
-        vec2 tmp_pos = p_rot * position;
+        vec3 tmp_pos = p_view * vec3(position, 1.0);
         gl_Position = vec4(tmp_pos.x, tmp_pos.y, 0.0, 1.0);
     }
     "#;
@@ -169,39 +541,85 @@ fn main() {
     }
     "#;
 
+    // a cross-fade covers the whole window, finding where it falls in each image from where
+    // that image is drawn
+    let fade_vertex_shader_src = r#"
+    #version 410
+    in vec2 position;
+    out vec2 old_coords;
+    out vec2 new_coords;
+
+    uniform mat3 old_view;
+    uniform mat3 new_view;
+
+    void main() {
+        vec3 point = vec3(position, 1.0);
+        old_coords = (inverse(old_view) * point).xy * 0.5 + 0.5;
+        new_coords = (inverse(new_view) * point).xy * 0.5 + 0.5;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+    "#;
+
+    let fade_shader_src = r#"
+    #version 410
+    in vec2 old_coords;
+    in vec2 new_coords;
+    out vec4 color;
+
+    uniform sampler2D old_tex;
+    uniform sampler2D new_tex;
+    uniform float progress;
+
+    // premultiplied, and clear outside the image so the background shows through
+    vec4 covering(sampler2D tex, vec2 coords) {
+        vec4 texel = texture(tex, coords);
+        bool inside = all(greaterThanEqual(coords, vec2(0.0)))
+            && all(lessThanEqual(coords, vec2(1.0)));
+        return inside ? vec4(texel.rgb * texel.a, texel.a) : vec4(0.0);
+    }
+
+    void main() {
+        color = mix(covering(old_tex, old_coords), covering(new_tex, new_coords), progress);
+    }
+    "#;
+
+    let overlay_shader_src = r#"
+    #version 410
+    out vec4 color;
+
+    uniform vec4 fill;
+
+    void main() {
+        color = fill;
+    }
+    "#;
+
     let program =
         glium::Program::from_source(&display, vertex_shader_src, fragment_shader_src, None)
             .unwrap();
+    let overlay_program =
+        glium::Program::from_source(&display, vertex_shader_src, overlay_shader_src, None).unwrap();
+    let fade_program =
+        glium::Program::from_source(&display, fade_vertex_shader_src, fade_shader_src, None)
+            .unwrap();
 
     let mut state = State::default();
-    if args.len() > 1 {
-        state.image_uri = match args.get(1) {
-            None => {
-                info!("invalid argument");
-                "".to_string()
-            }
-            Some(arg) => match arg.to_str() {
-                None => {
-                    info!("arguments was not string");
-                    "".to_string()
-                }
-                Some(path) => path.to_string()
-            }
-        };
-            args.get(1).unwrap().to_str().unwrap().to_string();
-        state.directory = Path::new(&state.image_uri)
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+    if let Err(err) = state.open_options(&options) {
+        eprintln!("femtophotos: {}", err);
+        process::exit(1);
+    }
+    let config = load_config(&options.settings);
+    state.encode = config.encode;
+    state.backup = config.backup;
+    state.wrap = config.wrap;
+    let fade = config.slideshow.fade;
+    let mut slideshow = Slideshow::new(state.slideshow.unwrap_or(config.slideshow.interval));
+    if state.slideshow.is_some() {
+        slideshow.start(Instant::now());
     }
     state.load_img();
 
-    let mut texture: SrgbTexture2d;
-    let mut image_size: (u32, u32) = (0, 0);
-
-    (texture, image_size) = match load_texture(&display, &state) {
+    let mut loaded = match load_texture(&display, &state) {
         Ok(res) => res,
         Err(err) => {
             info!("line 191: {:?}", err);
@@ -211,17 +629,52 @@ fn main() {
 
     info!("First texture loaded");
 
-    display.gl_window().window().set_title(&format!(
-        "FemtoPhotos: {}",
-        Path::new(&state.image_uri)
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-    ));
+    display
+        .gl_window()
+        .window()
+        .set_title(&window_title(&state, &loaded));
 
     state.image_changed = false;
 
+    let proxy = event_loop.create_proxy();
+    let preloader = Preloader::new(cache::CACHE_BUDGET, proxy.clone());
+    for path in state.neighbours(cache::PRELOAD_DISTANCE) {
+        preloader.request(&path);
+    }
+    // directories are watched for as long as the window is open, files given by name are not
+    let _watcher = match state.source.directory() {
+        None => None,
+        Some(dir) => {
+            let proxy = proxy.clone();
+            let watched = watch::watch(dir, state.source.is_recursive(), move |change| {
+                // the event loop is gone when the window is closing
+                let _ = proxy.send_event(UserEvent::FileChanged(change));
+            });
+            match watched {
+                Ok(watcher) => Some(watcher),
+                Err(err) => {
+                    warn!("could not watch {:?}: {}", dir, err);
+                    None
+                }
+            }
+        }
+    };
+    // the image being decoded in the background while the previous one stays on screen
+    let mut pending: Option<PathBuf> = None;
+    // where the current texture was last drawn, kept while the next image is pending as the
+    // orientation and view have already moved on to it
+    let mut drawn_view: Option<[[f32; 3]; 3]> = None;
+    // where the image was drawn when the slideshow moved on, until the next one is shown
+    let mut fade_from: Option<[[f32; 3]; 3]> = None;
+    let mut outgoing: Option<Outgoing> = None;
+    // when to next check whether a file being followed has been written
+    let mut settle_at: Option<Instant> = None;
+    let mut cursor_hide_at = options
+        .fullscreen
+        .then(|| Instant::now() + CURSOR_HIDE_DELAY);
+    // when the mouse was last clicked, and whether that paused the slideshow
+    let mut last_click: Option<(Instant, bool)> = None;
+
     info!("Render loop started");
 
     event_loop.run(move |ev, _, control_flow| {
@@ -248,6 +701,11 @@ fn main() {
             glutin::event::Event::WindowEvent { event, .. } => match event {
                 glutin::event::WindowEvent::CloseRequested => {
                     debug!("Close requested");
+                    if let (Some(geometry), Some(path)) = (windowed, &geometry_path) {
+                        if let Err(err) = geometry.save(path) {
+                            warn!("could not save the window geometry to {:?}: {}", path, err);
+                        }
+                    }
                     *control_flow = glutin::event_loop::ControlFlow::Exit;
                     state.running = false;
                     state.needs_redraw = false;
@@ -260,9 +718,57 @@ fn main() {
                     }
                     state.needs_redraw = false;
                 }
-                glutin::event::WindowEvent::CursorMoved { position, .. } => {
-                    state.mouse_position = Some((position.x as u32, position.y as u32));
+                glutin::event::WindowEvent::Resized(_) => {
+                    windowed = window_geometry(display.gl_window().window()).or(windowed);
+                }
+                glutin::event::WindowEvent::Moved(_) => {
                     state.needs_redraw = false;
+                    windowed = window_geometry(display.gl_window().window()).or(windowed);
+                }
+                glutin::event::WindowEvent::CursorMoved { position, .. } => {
+                    if cursor_hide_at.is_some()
+                        || display.gl_window().window().fullscreen().is_some()
+                    {
+                        display.gl_window().window().set_cursor_visible(true);
+                        cursor_hide_at = Some(Instant::now() + CURSOR_HIDE_DELAY);
+                    }
+                    let position = (position.x as u32, position.y as u32);
+                    match (state.drag_origin, state.mouse_position) {
+                        (Some(_), Some(last)) if !state.view.is_fit() => {
+                            state.view.pan_by((
+                                position.0 as f32 - last.0 as f32,
+                                last.1 as f32 - position.1 as f32,
+                            ));
+                        }
+                        _ => state.needs_redraw = false,
+                    }
+                    state.mouse_position = Some(position);
+                }
+                glutin::event::WindowEvent::MouseWheel { delta, .. } => {
+                    if slideshow.pause() {
+                        fade_from = None;
+                        state.status = Some(slideshow_status(&slideshow));
+                        display
+                            .gl_window()
+                            .window()
+                            .set_title(&window_title(&state, &loaded));
+                    }
+                    let notches = match delta {
+                        glutin::event::MouseScrollDelta::LineDelta(_, y) => y,
+                        glutin::event::MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 50.0,
+                    };
+                    let d_size = display.get_framebuffer_dimensions();
+                    let cursor = match state.mouse_position {
+                        Some(position) => view::window_to_view(position, d_size),
+                        None => (0.0, 0.0),
+                    };
+                    state.view.zoom_at(
+                        view::ZOOM_STEP.powf(notches),
+                        cursor,
+                        &state.orientation,
+                        d_size,
+                        loaded.size,
+                    );
                 }
                 glutin::event::WindowEvent::CursorLeft { .. } => {
                     state.mouse_position = None;
@@ -274,8 +780,11 @@ fn main() {
                         state.drag_origin = Some((touch.location.x as u32, touch.location.y as u32))
                     }
                     glutin::event::TouchPhase::Ended => {
-                        if let (Some(start), Some(end)) = (state.drag_origin, state.mouse_position)
-                        {
+                        if let (Some(start), Some(end), true) = (
+                            state.drag_origin.take(),
+                            state.mouse_position,
+                            state.view.is_fit(),
+                        ) {
                             trace!(
                                 "mouse: start@{} end@{} prev:{} next:{}",
                                 start.0,
@@ -308,12 +817,10 @@ fn main() {
                 | glutin::event::WindowEvent::KeyboardInput { .. }
                 | glutin::event::WindowEvent::Ime(_)
                 | glutin::event::WindowEvent::CursorEntered { .. }
-                | glutin::event::WindowEvent::MouseWheel { .. }
                 | glutin::event::WindowEvent::MouseInput { .. }
                 | glutin::event::WindowEvent::TouchpadPressure { .. }
                 | glutin::event::WindowEvent::AxisMotion { .. }
-                | glutin::event::WindowEvent::Occluded(_)
-                | glutin::event::WindowEvent::Moved { .. } => {
+                | glutin::event::WindowEvent::Occluded(_) => {
                     state.needs_redraw = false;
                 }
                 _ => (),
@@ -331,12 +838,48 @@ fn main() {
                     state: button_state,
                 } => match (button, button_state) {
                     (1, ElementState::Pressed) => {
-                        state.needs_redraw = false;
+                        let now = Instant::now();
+                        match last_click.take() {
+                            Some((at, paused))
+                                if now - at <= DOUBLE_CLICK && state.mouse_position.is_some() =>
+                            {
+                                // like F and F11 this leaves the slideshow running, so the pause
+                                // from the first click is undone
+                                if paused {
+                                    slideshow.start(now);
+                                    state.status = Some(slideshow_status(&slideshow));
+                                }
+                                let window = display.gl_window();
+                                let window = window.window();
+                                cursor_hide_at =
+                                    set_fullscreen(window, window.fullscreen().is_none());
+                                state.needs_redraw = paused;
+                            }
+                            _ => {
+                                let paused = slideshow.pause();
+                                if paused {
+                                    fade_from = None;
+                                    state.status = Some(slideshow_status(&slideshow));
+                                }
+                                last_click = Some((now, paused));
+                                state.needs_redraw = paused;
+                            }
+                        }
+                        if state.needs_redraw {
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
                         state.drag_origin = state.mouse_position;
                     }
                     (1, ElementState::Released) => {
-                        if let (Some(start), Some(end)) = (state.drag_origin, state.mouse_position)
-                        {
+                        // dragging pans while zoomed in, and swipes between images otherwise
+                        if let (Some(start), Some(end), true) = (
+                            state.drag_origin.take(),
+                            state.mouse_position,
+                            state.view.is_fit(),
+                        ) {
                             trace!(
                                 "touch: start@{} end@{} prev:{} next:{}",
                                 start.0,
@@ -356,16 +899,147 @@ fn main() {
                     } //_ => println!("{:?}", event),
                 },
                 glutin::event::DeviceEvent::Key(k) => {
+                    // taking over browsing pauses the slideshow, F5 starts it again
+                    if k.state == ElementState::Pressed
+                        && k.virtual_keycode
+                            .is_some_and(|key| !leaves_slideshow_running(key))
+                        && slideshow.pause()
+                    {
+                        fade_from = None;
+                        state.status = Some(slideshow_status(&slideshow));
+                        display
+                            .gl_window()
+                            .window()
+                            .set_title(&window_title(&state, &loaded));
+                    }
                     match (k.virtual_keycode, k.state, state.modifiers) {
+                        // while a jump is typed the number keys go to it rather than the view
+                        (Some(key), ElementState::Pressed, _) if state.jump.is_some() => {
+                            state.needs_redraw = false;
+                            match (key, digit(key), state.jump.as_mut()) {
+                                (_, Some(digit), Some(jump)) => jump.push(digit),
+                                (VirtualKeyCode::Back, _, Some(jump)) => {
+                                    jump.pop();
+                                }
+                                (VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter, _, _) => {
+                                    let jump = state.jump.take().unwrap_or_default();
+                                    match jump.parse::<usize>() {
+                                        Ok(number @ 1..) => state.go_to_img(number - 1),
+                                        _ => info!("no image {:?} to jump to", jump),
+                                    }
+                                    state.needs_redraw = state.image_changed;
+                                }
+                                (VirtualKeyCode::Escape, _, _) => state.jump = None,
+                                _ => (),
+                            }
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        // while an overwrite is asked about only the answer is taken
+                        (Some(key), ElementState::Pressed, _) if state.overwrite.is_some() => {
+                            match key {
+                                VirtualKeyCode::Y
+                                | VirtualKeyCode::Return
+                                | VirtualKeyCode::NumpadEnter => {
+                                    if let Some(file) = state.overwrite.take() {
+                                        start_save(file, &state, &loaded, &proxy);
+                                    }
+                                }
+                                VirtualKeyCode::N | VirtualKeyCode::Escape => {
+                                    if let Some(file) = state.overwrite.take() {
+                                        info!("Overwrite of {:?} cancelled", file);
+                                    }
+                                }
+                                _ => state.needs_redraw = false,
+                            }
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        (_, ElementState::Released, _) if state.overwrite.is_some() => {
+                            state.needs_redraw = false;
+                        }
+                        (Some(VirtualKeyCode::G), ElementState::Pressed, None) => {
+                            state.needs_redraw = false;
+                            state.jump = Some(String::new());
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        (
+                            Some(VirtualKeyCode::F11 | VirtualKeyCode::F),
+                            ElementState::Pressed,
+                            None,
+                        ) => {
+                            // redrawn once the window has been resized
+                            state.needs_redraw = false;
+                            let window = display.gl_window();
+                            let window = window.window();
+                            cursor_hide_at = set_fullscreen(window, window.fullscreen().is_none());
+                        }
+                        (Some(VirtualKeyCode::Escape), ElementState::Pressed, None) => {
+                            state.needs_redraw = false;
+                            let window = display.gl_window();
+                            if window.window().fullscreen().is_some() {
+                                cursor_hide_at = set_fullscreen(window.window(), false);
+                            }
+                        }
+                        (Some(VirtualKeyCode::Home), ElementState::Pressed, _) => {
+                            state.first_img();
+                        }
+                        (Some(VirtualKeyCode::End), ElementState::Pressed, _) => {
+                            state.last_img();
+                        }
+                        // as with saving, neither the placeholder for a broken image nor one
+                        // that no longer matches `state` is written back
+                        (Some(VirtualKeyCode::R), ElementState::Pressed, Some(mods))
+                            if mods.contains(ModifiersState::CTRL)
+                                && (loaded.error.is_some() || pending.is_some()) =>
+                        {
+                            state.needs_redraw = false;
+                        }
                         (Some(VirtualKeyCode::R), ElementState::Pressed, Some(mods)) => {
-                            if mods.contains(ModifiersState::SHIFT) {
-                                state.rotation = state.rotation.anticlockwise();
+                            if mods.contains(ModifiersState::CTRL) {
+                                // the file is rewritten as shown, then reloaded when done
+                                state.needs_redraw = false;
+                                if state.orientation == Orientation::default() {
+                                    state.status = Some(String::from("already upright"));
+                                    display
+                                        .gl_window()
+                                        .window()
+                                        .set_title(&window_title(&state, &loaded));
+                                } else {
+                                    let path = PathBuf::from(&state.image_uri);
+                                    let orientation = state.orientation;
+                                    let trim = mods.contains(ModifiersState::SHIFT);
+                                    let backup = state.backup;
+                                    let proxy = proxy.clone();
+                                    thread::spawn(move || {
+                                        let result =
+                                            jpeg::rotate_in_place(&path, orientation, trim, backup)
+                                                .map(|()| path);
+                                        // the event loop is gone when the window is closing
+                                        let _ = proxy.send_event(UserEvent::Rotated(result));
+                                    });
+                                }
+                            } else if mods.contains(ModifiersState::SHIFT) {
+                                state.orientation = state.orientation.anticlockwise();
                             } else {
-                                state.rotation = state.rotation.clockwise();
+                                state.orientation = state.orientation.clockwise();
                             }
                         }
                         (Some(VirtualKeyCode::R), ElementState::Pressed, None) => {
-                            state.rotation = state.rotation.clockwise();
+                            state.orientation = state.orientation.clockwise();
+                        }
+                        (Some(VirtualKeyCode::H), ElementState::Pressed, None) => {
+                            state.orientation = state.orientation.flip_horizontal();
+                        }
+                        (Some(VirtualKeyCode::V), ElementState::Pressed, None) => {
+                            state.orientation = state.orientation.flip_vertical();
                         }
                         (Some(VirtualKeyCode::Space), ElementState::Pressed, None) => {
                             state.next_img();
@@ -376,33 +1050,180 @@ fn main() {
                         (Some(VirtualKeyCode::Left), ElementState::Pressed, None) => {
                             state.prev_img();
                         }
-                        (Some(VirtualKeyCode::S), ElementState::Released, None) => {
+                        (
+                            Some(
+                                VirtualKeyCode::Equals
+                                | VirtualKeyCode::Plus
+                                | VirtualKeyCode::NumpadAdd,
+                            ),
+                            ElementState::Pressed,
+                            _,
+                        ) => {
+                            state.view.zoom_at(
+                                view::ZOOM_STEP,
+                                (0.0, 0.0),
+                                &state.orientation,
+                                display.get_framebuffer_dimensions(),
+                                loaded.size,
+                            );
+                        }
+                        (
+                            Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract),
+                            ElementState::Pressed,
+                            _,
+                        ) => {
+                            state.view.zoom_at(
+                                1.0 / view::ZOOM_STEP,
+                                (0.0, 0.0),
+                                &state.orientation,
+                                display.get_framebuffer_dimensions(),
+                                loaded.size,
+                            );
+                        }
+                        (
+                            Some(VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0),
+                            ElementState::Pressed,
+                            None,
+                        ) => {
+                            state.view.fit();
+                        }
+                        (
+                            Some(VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1),
+                            ElementState::Pressed,
+                            None,
+                        ) => {
+                            state.view.actual_pixels();
+                        }
+                        (Some(VirtualKeyCode::O), ElementState::Pressed, mods) => {
+                            // the listing is sorted again when next browsed
+                            state.needs_redraw = false;
+                            if mods.is_some_and(|mods| mods.contains(ModifiersState::SHIFT)) {
+                                state.set_sort(state.sort, !state.reverse);
+                            } else {
+                                state.set_sort(state.sort.next(), state.reverse);
+                            }
+                            info!("Browsing {}", state.order_text());
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        (Some(VirtualKeyCode::X), ElementState::Pressed, None) => {
+                            state.needs_redraw = false;
+                            state.set_shuffle(!state.shuffle);
+                            info!("Browsing {}", state.order_text());
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        (Some(VirtualKeyCode::Back), ElementState::Pressed, None) => {
+                            state.back_img();
+                        }
+                        (Some(VirtualKeyCode::F5), ElementState::Pressed, None) => {
+                            // redrawn to show or hide the countdown
+                            slideshow.toggle(Instant::now());
+                            fade_from = None;
+                            state.status = Some(slideshow_status(&slideshow));
+                            info!("{}", slideshow_status(&slideshow));
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        (Some(VirtualKeyCode::N), ElementState::Pressed, None) => {
+                            state.needs_redraw = false;
+                            state.follow = !state.follow;
+                            state.status = Some(String::from(if state.follow {
+                                "opening new images"
+                            } else {
+                                "not opening new images"
+                            }));
+                            display
+                                .gl_window()
+                                .window()
+                                .set_title(&window_title(&state, &loaded));
+                        }
+                        (Some(VirtualKeyCode::P), ElementState::Pressed, None) => {
+                            match &mut loaded.playback {
+                                Some(playback) => {
+                                    playback.toggle_pause(Instant::now());
+                                    display
+                                        .gl_window()
+                                        .window()
+                                        .set_title(&window_title(&state, &loaded));
+                                }
+                                None => state.needs_redraw = false,
+                            }
+                        }
+                        (Some(VirtualKeyCode::Period), ElementState::Pressed, None) => {
+                            match &mut loaded.playback {
+                                Some(playback) => {
+                                    playback.step_forward();
+                                    display
+                                        .gl_window()
+                                        .window()
+                                        .set_title(&window_title(&state, &loaded));
+                                }
+                                None => state.needs_redraw = false,
+                            }
+                        }
+                        (Some(VirtualKeyCode::Comma), ElementState::Pressed, None) => {
+                            match &mut loaded.playback {
+                                Some(playback) => {
+                                    playback.step_back();
+                                    display
+                                        .gl_window()
+                                        .window()
+                                        .set_title(&window_title(&state, &loaded));
+                                }
+                                None => state.needs_redraw = false,
+                            }
+                        }
+                        // the placeholder shown for a broken image is not worth saving, and while
+                        // the next image decodes the one shown no longer matches `state`
+                        (Some(VirtualKeyCode::S), ElementState::Released, None)
+                            if loaded.error.is_none() && pending.is_none() =>
+                        {
                             state.needs_redraw = false;
-                            let file = FileDialog::new()
+                            // rfd cannot report which filter was picked, so a name typed without
+                            // an extension is saved in the format of the first filter
+                            let default_format =
+                                image_saving::save_format(Path::new(&state.image_uri))
+                                    .unwrap_or(Format::Jpeg);
+                            let mut dialog = FileDialog::new()
                                 .set_directory(Path::new(&state.directory))
-                                .set_file_name(
-                                    Path::new(&state.image_uri)
-                                        .file_name()
-                                        .unwrap()
-                                        .to_str()
-                                        .unwrap(),
-                                )
-                                .add_filter("JPG", &["jpg", "JPG", "jpeg", "JPEG"])
-                                .add_filter("PNG", &["png", "PNG"])
-                                .add_filter("QOI", &["qoi", "QOI"])
-                                .save_file();
-
-                            info!("Saving to {:?}", file);
-
-                            let buf: image_saving::RGBAImageData =
-                                texture.read_to_pixel_buffer().read_as_texture_2d().unwrap();
-                            let size = (texture.width(), texture.height());
-
-                            thread::spawn(move || {
-                                let data: Vec<u8> = flatten(buf.data);
-
-                                save_image(data, size.0, size.1, file.unwrap().as_path());
+                                .set_file_name(image_name(&state));
+                            let others = image_saving::save_formats()
+                                .filter(|format| *format != default_format);
+                            for format in std::iter::once(default_format).chain(others) {
+                                let info = format.info();
+                                let extensions: Vec<String> = info
+                                    .extensions
+                                    .iter()
+                                    .flat_map(|ext| [ext.to_string(), ext.to_ascii_uppercase()])
+                                    .collect();
+                                let extensions: Vec<&str> =
+                                    extensions.iter().map(String::as_str).collect();
+                                dialog = dialog.add_filter(info.name, &extensions);
+                            }
+
+                            let file = dialog.save_file().map(|file| {
+                                image_saving::with_default_extension(file, default_format)
                             });
+                            match file {
+                                None => info!("Save cancelled"),
+                                // asked about over the image before it is replaced
+                                Some(file) if is_same_file(&file, Path::new(&state.image_uri)) => {
+                                    state.overwrite = Some(file);
+                                    state.needs_redraw = true;
+                                    display
+                                        .gl_window()
+                                        .window()
+                                        .set_title(&window_title(&state, &loaded));
+                                }
+                                Some(file) => start_save(file, &state, &loaded, &proxy),
+                            }
                         }
                         _ => {
                             state.needs_redraw = false;
@@ -413,10 +1234,140 @@ fn main() {
                     state.needs_redraw = false;
                 }
             },
-            glutin::event::Event::NewEvents(cause) => {
-                // println!("{:?}", cause);
-                if cause == glutin::event::StartCause::Poll {
+            glutin::event::Event::NewEvents(glutin::event::StartCause::Poll) => {
+                state.needs_redraw = false;
+            }
+            glutin::event::Event::NewEvents(glutin::event::StartCause::ResumeTimeReached {
+                ..
+            }) => {
+                let now = Instant::now();
+                state.needs_redraw = match &mut loaded.playback {
+                    Some(playback) => playback.advance(now),
+                    None => false,
+                };
+                if cursor_hide_at.is_some_and(|at| at <= now) {
+                    cursor_hide_at = None;
+                    display.gl_window().window().set_cursor_visible(false);
+                }
+                if settle_at.is_some_and(|at| at <= now) {
+                    settle_at = state
+                        .settle_arriving()
+                        .then(|| now + watch::SETTLE_INTERVAL);
+                    state.needs_redraw |= state.image_changed;
+                }
+                // the countdown and any fade move on every frame
+                state.needs_redraw |= slideshow.is_running() || outgoing.is_some();
+                if slideshow.due(now) {
+                    let p_view = state.view.to_mat(
+                        &state.orientation,
+                        display.get_framebuffer_dimensions(),
+                        loaded.size,
+                    );
+                    state.next_img();
+                    if !state.image_changed {
+                        slideshow.pause();
+                        state.status = Some(String::from("slideshow finished"));
+                        display
+                            .gl_window()
+                            .window()
+                            .set_title(&window_title(&state, &loaded));
+                    } else if !fade.is_zero() {
+                        fade_from = Some(p_view);
+                    }
+                }
+            }
+            glutin::event::Event::UserEvent(UserEvent::Preloaded(path)) => {
+                if pending.as_ref() == Some(&path) {
+                    pending = None;
+                    // a failed decode is retried here so the error and placeholder are shown
+                    let result = match preloader.get(&path) {
+                        Some(decoded) => upload_texture(&display, decoded),
+                        None => load_texture(&display, &state),
+                    };
+                    let previous = match result {
+                        Ok(res) => mem::replace(&mut loaded, res),
+                        Err(err) => panic!("{:?}", err),
+                    };
+                    outgoing = fade_from.take().map(|p_view| Outgoing {
+                        loaded: previous,
+                        p_view,
+                        fade: Fade::new(fade, Instant::now()),
+                    });
+                    display
+                        .gl_window()
+                        .window()
+                        .set_title(&window_title(&state, &loaded));
+                } else {
+                    state.needs_redraw = false;
+                }
+            }
+            glutin::event::Event::UserEvent(UserEvent::Saved(Ok(path)))
+                if is_same_file(&path, Path::new(&state.image_uri)) =>
+            {
+                // the orientation shown is now baked into the file
+                info!("Saved over {:?}", path);
+                state.load_img();
+                state.status = Some(format!("saved {}", path.display()));
+            }
+            glutin::event::Event::UserEvent(UserEvent::Saved(result)) => {
+                state.needs_redraw = false;
+                state.status = Some(match result {
+                    Ok(path) => {
+                        info!("Saved to {:?}", path);
+                        state.add_file(&path);
+                        format!("saved {}", path.display())
+                    }
+                    Err(err) => {
+                        warn!("{}", err);
+                        format!("could not save: {}", err.reason())
+                    }
+                });
+                display
+                    .gl_window()
+                    .window()
+                    .set_title(&window_title(&state, &loaded));
+            }
+            glutin::event::Event::UserEvent(UserEvent::Rotated(result)) => match result {
+                Ok(path) if path == Path::new(&state.image_uri) => {
+                    // rereads the now upright orientation, the cached pixels are stale
+                    state.load_img();
+                    state.status = Some(String::from("rotated losslessly"));
+                }
+                Ok(path) => {
+                    info!("Rotated {:?}", path);
+                    state.needs_redraw = false;
+                }
+                Err(err) => {
+                    warn!("{}", err);
                     state.needs_redraw = false;
+                    state.status = Some(format!("could not rotate: {}", err.reason()));
+                    display
+                        .gl_window()
+                        .window()
+                        .set_title(&window_title(&state, &loaded));
+                }
+            },
+            glutin::event::Event::UserEvent(UserEvent::FileChanged(change)) => {
+                debug!("{:?}", change);
+                match change {
+                    FileChange::Created(path) => {
+                        state.file_created(&path);
+                        settle_at = Some(Instant::now() + watch::SETTLE_INTERVAL);
+                    }
+                    FileChange::Added(path) => state.add_file(&path),
+                    FileChange::Removed(path) => state.remove_file(&path),
+                    FileChange::Written(path) => state.file_written(&path),
+                }
+                if state.arriving.is_none() {
+                    settle_at = None;
+                }
+                // the position in the title can change without the image changing
+                state.needs_redraw = state.image_changed;
+                if !state.image_changed {
+                    display
+                        .gl_window()
+                        .window()
+                        .set_title(&window_title(&state, &loaded));
                 }
             }
             glutin::event::Event::Suspended
@@ -433,58 +1384,139 @@ fn main() {
             // println!("{}", str_ev);
 
             if state.image_changed {
-                (texture, image_size) = match load_texture(&display, &state) {
-                    Ok(res) => res,
-                    Err(err) => panic!("{:?}", err),
-                };
-
-                display.gl_window().window().set_title(&format!(
-                    "FemtoPhotos: {}",
-                    Path::new(&state.image_uri)
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                ));
+                let path = PathBuf::from(&state.image_uri);
+                match preloader.get(&path) {
+                    Some(decoded) => {
+                        pending = None;
+                        let previous = match upload_texture(&display, decoded) {
+                            Ok(res) => mem::replace(&mut loaded, res),
+                            Err(err) => panic!("{:?}", err),
+                        };
+                        outgoing = fade_from.take().map(|p_view| Outgoing {
+                            loaded: previous,
+                            p_view,
+                            fade: Fade::new(fade, Instant::now()),
+                        });
+                        display
+                            .gl_window()
+                            .window()
+                            .set_title(&window_title(&state, &loaded));
+                    }
+                    None => {
+                        preloader.request(&path);
+                        display
+                            .gl_window()
+                            .window()
+                            .set_title(&format!("FemtoPhotos: {} (loading)", state.title_name()));
+                        pending = Some(path);
+                    }
+                }
+                for path in state.neighbours(cache::PRELOAD_DISTANCE) {
+                    preloader.request(&path);
+                }
+                slideshow.restart(Instant::now());
 
                 state.image_changed = false;
             }
 
-            let uniforms = uniform! {
-                p_rot: state.rotation.to_mat(display.get_framebuffer_dimensions(), image_size),
-                tex: &texture,
+            let now = Instant::now();
+            let d_size = display.get_framebuffer_dimensions();
+            let params = DrawParameters {
+                blend: Blend::alpha_blending(),
+                ..Default::default()
             };
+            let progress = outgoing.as_ref().map_or(1.0, |out| out.fade.progress(now));
+            if progress >= 1.0 {
+                outgoing = None;
+            }
 
             let mut target = display.draw();
             target.clear_color(0.2, 0.2, 0.2, 1.0);
 
-            target
-                .draw(
-                    &vertex_buffer,
-                    indices,
-                    &program,
-                    &uniforms,
-                    &DrawParameters {
-                        blend: Blend::alpha_blending(),
+            let p_view = match (&pending, drawn_view) {
+                (Some(_), Some(p_view)) => p_view,
+                _ => state.view.to_mat(&state.orientation, d_size, loaded.size),
+            };
+            drawn_view = Some(p_view);
+            match &outgoing {
+                // the images are mixed in one pass, so where only the old one covers it fades
+                // into the background rather than showing through the new one
+                Some(out) => {
+                    let uniforms = uniform! {
+                        old_view: out.p_view,
+                        new_view: p_view,
+                        old_tex: out.loaded.current(),
+                        new_tex: loaded.current(),
+                        progress: progress,
+                    };
+                    let params = DrawParameters {
+                        blend: PREMULTIPLIED_BLENDING,
                         ..Default::default()
-                    },
-                )
-                .unwrap();
+                    };
+                    target
+                        .draw(&vertex_buffer, indices, &fade_program, &uniforms, &params)
+                        .unwrap();
+                }
+                None => {
+                    let uniforms = uniform! {
+                        p_view: p_view,
+                        tex: loaded.current(),
+                    };
+                    target
+                        .draw(&vertex_buffer, indices, &program, &uniforms, &params)
+                        .unwrap();
+                }
+            }
+
+            if let Some(remaining) = slideshow.remaining(now) {
+                let uniforms = uniform! {
+                    p_view: slideshow::bar_mat(remaining, d_size),
+                    fill: [1.0, 1.0, 1.0, 0.6f32],
+                };
+                target
+                    .draw(
+                        &vertex_buffer,
+                        indices,
+                        &overlay_program,
+                        &uniforms,
+                        &params,
+                    )
+                    .unwrap();
+            }
+
+            // the image is dimmed while asking whether to overwrite it
+            if state.overwrite.is_some() {
+                let uniforms = uniform! {
+                    p_view: FULL_WINDOW,
+                    fill: [0.0, 0.0, 0.0, 0.5f32],
+                };
+                target
+                    .draw(
+                        &vertex_buffer,
+                        indices,
+                        &overlay_program,
+                        &uniforms,
+                        &params,
+                    )
+                    .unwrap();
+            }
 
             target.finish().unwrap();
             state.needs_redraw = false;
         }
-    });
-}
 
-#[no_mangle]
-#[inline(never)]
-fn flatten(data: Vec<(u8, u8, u8, u8)>) -> Vec<u8> {
-    let size = data.capacity();
-    let mut result = data;
-    unsafe {
-        result.set_len(size * 4);
-        #[allow(clippy::unsound_collection_transmute)]
-        std::mem::transmute(result)
-    }
+        if *control_flow == glutin::event_loop::ControlFlow::Wait {
+            let now = Instant::now();
+            let frame = loaded.playback.as_ref().and_then(Playback::next_deadline);
+            let fading = outgoing.as_ref().map(|_| now + slideshow::FRAME_INTERVAL);
+            let deadlines = frame
+                .into_iter()
+                .chain(fading)
+                .chain(cursor_hide_at)
+                .chain(settle_at);
+            if let Some(deadline) = deadlines.chain(slideshow.next_deadline(now)).min() {
+                *control_flow = glutin::event_loop::ControlFlow::WaitUntil(deadline);
+            }
+        }
+    });
 }
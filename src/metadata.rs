@@ -0,0 +1,495 @@
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+};
+
+use exif::Tag;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use log::{debug, warn};
+
+use crate::format::Format;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+/// The most a JPEG marker segment can hold after its length
+const MAX_SEGMENT: usize = 65533;
+
+const APP1: u8 = 0xE1;
+const APP2: u8 = 0xE2;
+
+/// Which metadata to leave out of saved images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strip {
+    #[default]
+    None,
+    /// The GPS tags, and any XMP as it may repeat them
+    Gps,
+    All,
+}
+
+impl Strip {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strip::None => "none",
+            Strip::Gps => "gps",
+            Strip::All => "all",
+        }
+    }
+}
+
+impl fmt::Display for Strip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Strip {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Strip::None, Strip::Gps, Strip::All]
+            .into_iter()
+            .find(|strip| strip.name() == s)
+            .ok_or(())
+    }
+}
+
+/// Metadata carried over from a loaded image into the files it is saved as
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// A TIFF structure holding the EXIF tags
+    pub exif: Option<Vec<u8>>,
+    /// An ICC colour profile
+    pub icc: Option<Vec<u8>>,
+    /// An XMP packet
+    pub xmp: Option<String>,
+}
+
+impl Metadata {
+    /// Reads the metadata of the image at `path`, leaving out whatever cannot be read
+    pub fn read(path: &Path) -> Metadata {
+        let exif = fs::File::open(path)
+            .map_err(exif::Error::from)
+            .and_then(|file| exif::Reader::new().read_from_container(&mut io::BufReader::new(file)))
+            .map(|exif| exif.buf().to_vec());
+        let mut metadata = match Format::detect(path) {
+            Ok(Some(format @ (Format::Jpeg | Format::Png))) => match fs::read(path) {
+                Ok(data) if format == Format::Jpeg => Metadata::from_jpeg(&data),
+                Ok(data) => Metadata::from_png(&data),
+                Err(err) => {
+                    warn!("metadata {:?}: {}", path, err);
+                    Metadata::default()
+                }
+            },
+            _ => Metadata::default(),
+        };
+        match exif {
+            Ok(exif) => metadata.exif = Some(exif),
+            Err(err) => debug!("metadata {:?}: no exif: {:?}", path, err),
+        }
+        metadata
+    }
+
+    /// The ICC profile and XMP packet of a JPEG
+    fn from_jpeg(data: &[u8]) -> Metadata {
+        let mut metadata = Metadata::default();
+        let mut icc_chunks = Vec::new();
+        for (marker, body) in jpeg_segments(data) {
+            let body = &data[body];
+            if marker == APP1 && body.starts_with(XMP_HEADER) {
+                let xmp = String::from_utf8_lossy(&body[XMP_HEADER.len()..]);
+                metadata.xmp = Some(xmp.into_owned());
+            } else if marker == APP2 && body.len() > ICC_HEADER.len() + 2 {
+                if let Some(chunk) = body.strip_prefix(ICC_HEADER) {
+                    // a profile too big for one segment is split, each part numbered from 1
+                    icc_chunks.push((chunk[0], &chunk[2..]));
+                }
+            }
+        }
+        if !icc_chunks.is_empty() {
+            icc_chunks.sort_by_key(|(sequence, _)| *sequence);
+            let icc = icc_chunks
+                .iter()
+                .flat_map(|(_, chunk)| chunk.iter().copied());
+            metadata.icc = Some(icc.collect());
+        }
+        metadata
+    }
+
+    /// The ICC profile and XMP packet of a PNG
+    ///
+    /// The chunks are read by hand, as the png crate does not finish inflating iCCP profiles.
+    fn from_png(data: &[u8]) -> Metadata {
+        let mut metadata = Metadata::default();
+        for (kind, body) in png_chunks(data) {
+            match kind {
+                b"iCCP" => metadata.icc = png_icc(body),
+                b"iTXt" => {
+                    if let Some(xmp) = png_xmp(body) {
+                        metadata.xmp = Some(xmp);
+                    }
+                }
+                _ => (),
+            }
+        }
+        metadata
+    }
+
+    /// Leaves out what `strip` asks for
+    pub fn strip(&mut self, strip: Strip) {
+        match strip {
+            Strip::None => (),
+            Strip::Gps => {
+                if let Some(exif) = &mut self.exif {
+                    if remove_gps(exif).is_none() {
+                        // unreadable tags could still hold a location
+                        warn!("metadata: could not find the GPS tags, dropping all EXIF");
+                        self.exif = None;
+                    }
+                }
+                self.xmp = None;
+            }
+            Strip::All => *self = Metadata::default(),
+        }
+    }
+
+    /// Sets the EXIF orientation to upright, for images saved the way they are shown
+    pub fn reset_orientation(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            reset_tiff_orientation(exif);
+        }
+    }
+
+    /// Inserts the metadata into an encoded JPEG, after its JFIF header if it has one
+    pub fn insert_into_jpeg(&self, jpeg: &[u8]) -> Vec<u8> {
+        let mut at = 2;
+        if let Some((marker, body)) = jpeg_segments(jpeg).first() {
+            if *marker == 0xE0 && jpeg[body.clone()].starts_with(b"JFIF\0") {
+                at = body.end;
+            }
+        }
+
+        let mut segments = Vec::new();
+        let mut push = |marker: u8, parts: &[&[u8]]| {
+            let len: usize = parts.iter().map(|part| part.len()).sum();
+            if len > MAX_SEGMENT {
+                warn!("metadata: {} bytes is too big for a JPEG segment", len);
+                return;
+            }
+            segments.extend([0xFF, marker]);
+            segments.extend((len as u16 + 2).to_be_bytes());
+            for part in parts {
+                segments.extend_from_slice(part);
+            }
+        };
+        if let Some(exif) = &self.exif {
+            push(APP1, &[EXIF_HEADER, exif]);
+        }
+        if let Some(xmp) = &self.xmp {
+            push(APP1, &[XMP_HEADER, xmp.as_bytes()]);
+        }
+        if let Some(icc) = &self.icc {
+            let chunks: Vec<&[u8]> = icc.chunks(MAX_SEGMENT - ICC_HEADER.len() - 2).collect();
+            for (n, chunk) in chunks.iter().enumerate() {
+                push(
+                    APP2,
+                    &[ICC_HEADER, &[n as u8 + 1, chunks.len() as u8], chunk],
+                );
+            }
+        }
+
+        let mut out = Vec::with_capacity(jpeg.len() + segments.len());
+        out.extend_from_slice(&jpeg[..at]);
+        out.append(&mut segments);
+        out.extend_from_slice(&jpeg[at..]);
+        out
+    }
+
+    /// Writes the metadata as eXIf, iCCP and iTXt chunks, before the image data
+    pub fn write_png_chunks<W: Write>(
+        &self,
+        writer: &mut png::Writer<W>,
+    ) -> Result<(), png::EncodingError> {
+        if let Some(icc) = &self.icc {
+            // a keyword, then compression method 0 for zlib
+            let mut chunk = b"ICC Profile\0\0".to_vec();
+            let mut encoder = ZlibEncoder::new(&mut chunk, Compression::default());
+            encoder.write_all(icc)?;
+            encoder.finish()?;
+            writer.write_chunk(png::chunk::ChunkType(*b"iCCP"), &chunk)?;
+        }
+        if let Some(exif) = &self.exif {
+            writer.write_chunk(png::chunk::ChunkType(*b"eXIf"), exif)?;
+        }
+        if let Some(xmp) = &self.xmp {
+            // uncompressed, with no language or translated keyword
+            let mut chunk = XMP_KEYWORD.as_bytes().to_vec();
+            chunk.extend_from_slice(b"\0\0\0\0\0");
+            chunk.extend_from_slice(xmp.as_bytes());
+            writer.write_chunk(png::chunk::ChunkType(*b"iTXt"), &chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// The marker and body of each segment of a JPEG before its image data
+fn jpeg_segments(jpeg: &[u8]) -> Vec<(u8, Range<usize>)> {
+    let mut segments = Vec::new();
+    let mut at = 2;
+    while at + 4 <= jpeg.len() && jpeg[at] == 0xFF {
+        let marker = jpeg[at + 1];
+        let len = u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+        // start of scan, after which there is only image data
+        if marker == 0xDA || len < 2 || at + 2 + len > jpeg.len() {
+            break;
+        }
+        segments.push((marker, at + 4..at + 2 + len));
+        at += 2 + len;
+    }
+    segments
+}
+
+/// The type and data of each chunk of a PNG
+fn png_chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    // after the signature, each chunk is a length, a type, the data and a checksum
+    let mut at = 8;
+    while at + 12 <= png.len() {
+        let len = u32::from_be_bytes([png[at], png[at + 1], png[at + 2], png[at + 3]]) as usize;
+        let data = match png.get(at + 8..at + 8 + len) {
+            Some(data) => data,
+            None => break,
+        };
+        chunks.push((&png[at + 4..at + 8], data));
+        at += 12 + len;
+    }
+    chunks
+}
+
+/// The profile in an iCCP chunk: a name, compression method 0, then the zlib stream
+fn png_icc(chunk: &[u8]) -> Option<Vec<u8>> {
+    let (_name, rest) = split_nul(chunk)?;
+    match rest.split_first()? {
+        (0, compressed) => inflate(compressed),
+        _ => None,
+    }
+}
+
+/// The packet in an iTXt chunk, if it holds XMP
+fn png_xmp(chunk: &[u8]) -> Option<String> {
+    let (keyword, rest) = split_nul(chunk)?;
+    if keyword != XMP_KEYWORD.as_bytes() {
+        return None;
+    }
+    // a compression flag and method, then the language and translated keyword
+    let (&compressed, rest) = rest.split_first()?;
+    let (_language, rest) = split_nul(rest.get(1..)?)?;
+    let (_translated, text) = split_nul(rest)?;
+    let text = match compressed {
+        0 => text.to_vec(),
+        _ => inflate(text)?,
+    };
+    String::from_utf8(text).ok()
+}
+
+fn split_nul(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let nul = bytes.iter().position(|b| *b == 0)?;
+    Some((&bytes[..nul], &bytes[nul + 1..]))
+}
+
+fn inflate(compressed: &[u8]) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(compressed).read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// Sets the EXIF orientation in the JPEG `data` to upright, returning whether it had one
+pub fn reset_jpeg_orientation(jpeg: &mut [u8]) -> bool {
+    let exif = jpeg_segments(jpeg)
+        .into_iter()
+        .find(|(marker, body)| *marker == APP1 && jpeg[body.clone()].starts_with(EXIF_HEADER));
+    match exif {
+        Some((_, body)) => reset_tiff_orientation(&mut jpeg[body][EXIF_HEADER.len()..]).is_some(),
+        None => false,
+    }
+}
+
+const SHORT: u16 = 3;
+
+/// Reads and writes the numbers of a TIFF structure in its byte order
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return None,
+        };
+        Some(Tiff { data, big_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn set_u16(&mut self, at: usize, value: u16) -> Option<()> {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.data.get_mut(at..at + 2)?.copy_from_slice(&bytes);
+        Some(())
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        Some(self.u32(4)? as usize)
+    }
+
+    /// The offset of each 12 byte entry of the IFD at `ifd`
+    fn entries(&self, ifd: usize) -> Option<impl Iterator<Item = usize>> {
+        let count = self.u16(ifd)? as usize;
+        Some((0..count).map(move |n| ifd + 2 + n * 12))
+    }
+
+    fn find(&self, ifd: usize, tag: Tag) -> Option<usize> {
+        self.entries(ifd)?
+            .find(|entry| self.u16(*entry) == Some(tag.number()))
+    }
+
+    /// The bytes an entry's values take up, stored in the entry itself when at most 4
+    fn value_len(&self, entry: usize) -> Option<usize> {
+        let size: usize = match self.u16(entry + 2)? {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => return None,
+        };
+        size.checked_mul(self.u32(entry + 4)? as usize)
+    }
+}
+
+/// Sets the orientation entry of the first IFD of `tiff` to 1
+fn reset_tiff_orientation(tiff: &mut [u8]) -> Option<()> {
+    let mut tiff = Tiff::new(tiff)?;
+    let entry = tiff.find(tiff.first_ifd()?, Tag::Orientation)?;
+    if tiff.u16(entry + 2)? != SHORT {
+        return None;
+    }
+    tiff.set_u16(entry + 8, 1)
+}
+
+/// Removes the GPS IFD from `tiff`, blanking its values so that none of them are left behind
+///
+/// Returns `None` if the structure could not be followed.
+fn remove_gps(tiff: &mut [u8]) -> Option<()> {
+    let mut tiff = Tiff::new(tiff)?;
+    let ifd = tiff.first_ifd()?;
+    let pointer = match tiff.find(ifd, Tag::GPSInfoIFDPointer) {
+        Some(pointer) => pointer,
+        None => return Some(()),
+    };
+
+    let gps = tiff.u32(pointer + 8)? as usize;
+    let entries: Vec<usize> = tiff.entries(gps)?.collect();
+    for entry in &entries {
+        let len = tiff.value_len(*entry)?;
+        if len > 4 {
+            let at = tiff.u32(entry + 8)? as usize;
+            tiff.data.get_mut(at..at.checked_add(len)?)?.fill(0);
+        }
+    }
+    // the entry count, the entries and the offset of the next IFD
+    tiff.data
+        .get_mut(gps..gps + 2 + entries.len() * 12 + 4)?
+        .fill(0);
+
+    // later entries and the next IFD offset move up over the pointer
+    let count = tiff.u16(ifd)?;
+    let end = ifd + 2 + count as usize * 12 + 4;
+    tiff.data.get(..end)?;
+    tiff.data.copy_within(pointer + 12..end, pointer);
+    tiff.data[end - 12..end].fill(0);
+    tiff.set_u16(ifd, count - 1)
+}
+
+#[cfg(test)]
+mod metadata_tests {
+    use super::*;
+    use exif::In;
+
+    fn fields(tiff: &[u8]) -> exif::Exif {
+        exif::Reader::new().read_raw(tiff.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_read_jpeg_metadata() {
+        let metadata = Metadata::read(Path::new("./test_images/metadata.jpg"));
+        let exif = fields(metadata.exif.as_ref().unwrap());
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+        // the profile is split over two segments
+        let icc: Vec<u8> = (0..300u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(metadata.icc, Some(icc));
+        assert!(metadata.xmp.unwrap().starts_with("<?xpacket"));
+
+        assert_eq!(
+            Metadata::read(Path::new("./test_images/dice.png")),
+            Metadata::default()
+        );
+    }
+
+    #[test]
+    fn test_strip_gps() {
+        let mut metadata = Metadata::read(Path::new("./test_images/metadata.jpg"));
+        metadata.strip(Strip::Gps);
+        let tiff = metadata.exif.as_ref().unwrap();
+        let exif = fields(tiff);
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+        // the latitude's seconds, 1234/100, are gone from the bytes as well
+        let seconds = [0, 0, 4, 0xD2, 0, 0, 0, 100];
+        assert!(!tiff.windows(8).any(|bytes| bytes == seconds));
+        assert!(metadata.icc.is_some());
+        assert_eq!(metadata.xmp, None);
+
+        metadata.strip(Strip::All);
+        assert_eq!(metadata, Metadata::default());
+    }
+
+    #[test]
+    fn test_reset_orientation() {
+        let mut metadata = Metadata::read(Path::new("./test_images/metadata.jpg"));
+        let orientation = |metadata: &Metadata| {
+            fields(metadata.exif.as_ref().unwrap())
+                .get_field(Tag::Orientation, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        };
+        assert_eq!(orientation(&metadata), Some(6));
+        metadata.reset_orientation();
+        assert_eq!(orientation(&metadata), Some(1));
+    }
+}
@@ -1,4 +1,9 @@
-#[derive(Debug, PartialEq)]
+use std::{fs, io, path::Path};
+
+use exif::{In, Tag};
+use image::DynamicImage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Rotation {
     Up,
     Right,
@@ -25,29 +30,147 @@ impl Rotation {
         }
     }
 
-    pub fn to_mat(&self, d_size: (u32, u32), i_size: (u32, u32)) -> [[f32; 2]; 2] {
-        let (mut t, mut s) = match self {
-            Rotation::Up | Rotation::Down => (
-                (d_size.1 * i_size.0) as f32 / (d_size.0 * i_size.1) as f32,
-                (d_size.0 * i_size.1) as f32 / (d_size.1 * i_size.0) as f32,
-            ),
-            Rotation::Right | Rotation::Left => (
-                (d_size.0 * i_size.0) as f32 / (d_size.1 * i_size.1) as f32,
-                (d_size.1 * i_size.1) as f32 / (d_size.0 * i_size.0) as f32,
-            ),
+    /// The unit matrix turning the image quad upright on screen, with texture rows flipped
+    pub fn orientation(&self) -> [[f32; 2]; 2] {
+        match self {
+            Rotation::Up => [[1.0, 0.0], [0.0, -1.0]],
+            Rotation::Right => [[0.0, -1.0], [-1.0, 0.0]],
+            Rotation::Down => [[-1.0, 0.0], [0.0, 1.0]],
+            Rotation::Left => [[0.0, 1.0], [1.0, 0.0]],
+        }
+    }
+
+    /// The size an image of `i_size` takes up on screen once rotated
+    pub fn rotated_size(&self, i_size: (u32, u32)) -> (u32, u32) {
+        match self {
+            Rotation::Up | Rotation::Down => i_size,
+            Rotation::Right | Rotation::Left => (i_size.1, i_size.0),
+        }
+    }
+}
+
+/// How an image is turned on screen: mirrored left to right first if `mirrored`, then rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub mirrored: bool,
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self {
+            rotation: Rotation::Up,
+            mirrored: false,
+        }
+    }
+}
+
+impl From<Rotation> for Orientation {
+    fn from(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            mirrored: false,
+        }
+    }
+}
+
+impl Orientation {
+    /// The orientation that displays an image tagged with EXIF orientation `value` upright
+    ///
+    /// Unknown values are treated as upright.
+    pub fn from_exif(value: u32) -> Orientation {
+        let (rotation, mirrored) = match value {
+            2 => (Rotation::Up, true),
+            3 => (Rotation::Down, false),
+            4 => (Rotation::Down, true),
+            5 => (Rotation::Left, true),
+            6 => (Rotation::Right, false),
+            7 => (Rotation::Right, true),
+            8 => (Rotation::Left, false),
+            _ => (Rotation::Up, false),
+        };
+        Orientation { rotation, mirrored }
+    }
+
+    /// Reads the EXIF orientation of the image at `path`, upright if it has none
+    pub fn read(path: &Path) -> Result<Orientation, exif::Error> {
+        let file = fs::File::open(path)?;
+        let exif = exif::Reader::new().read_from_container(&mut io::BufReader::new(file))?;
+        Ok(exif
+            .get_field(Tag::Orientation, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0))
+            .map_or_else(Orientation::default, Orientation::from_exif))
+    }
+
+    pub fn clockwise(&self) -> Orientation {
+        Orientation {
+            rotation: self.rotation.clockwise(),
+            ..*self
+        }
+    }
+
+    pub fn anticlockwise(&self) -> Orientation {
+        Orientation {
+            rotation: self.rotation.anticlockwise(),
+            ..*self
+        }
+    }
+
+    /// Mirrors the image as it currently appears on screen, left to right
+    pub fn flip_horizontal(&self) -> Orientation {
+        // mirroring after a rotation is the same as mirroring first and rotating the other way
+        let rotation = match self.rotation {
+            Rotation::Right => Rotation::Left,
+            Rotation::Left => Rotation::Right,
+            rotation => rotation,
         };
-        if t < 1.0 {
-            s = 1.0;
+        Orientation {
+            rotation,
+            mirrored: !self.mirrored,
+        }
+    }
+
+    /// Mirrors the image as it currently appears on screen, top to bottom
+    pub fn flip_vertical(&self) -> Orientation {
+        // a vertical flip is a horizontal flip turned half way round
+        let flipped = self.flip_horizontal();
+        Orientation {
+            rotation: flipped.rotation.clockwise().clockwise(),
+            ..flipped
+        }
+    }
+
+    /// The unit matrix turning the image quad upright on screen, with texture rows flipped
+    pub fn matrix(&self) -> [[f32; 2]; 2] {
+        let [x, y] = self.rotation.orientation();
+        if self.mirrored {
+            [[-x[0], -x[1]], y]
         } else {
-            t = 1.0;
+            [x, y]
         }
-        match self {
-            Rotation::Up => [[t, 0.0], [0.0, -s]],
-            Rotation::Right => [[0.0, -t], [-s, 0.0]],
-            Rotation::Down => [[-t, 0.0], [0.0, s]],
-            Rotation::Left => [[0.0, t], [s, 0.0]],
+    }
+
+    /// Turns the pixels of `image` the way it is shown on screen
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let mirrored;
+        let image = if self.mirrored {
+            mirrored = image.fliph();
+            &mirrored
+        } else {
+            image
+        };
+        match self.rotation {
+            Rotation::Up => image.clone(),
+            Rotation::Right => image.rotate90(),
+            Rotation::Down => image.rotate180(),
+            Rotation::Left => image.rotate270(),
         }
     }
+
+    /// The size an image of `i_size` takes up on screen once oriented
+    pub fn rotated_size(&self, i_size: (u32, u32)) -> (u32, u32) {
+        self.rotation.rotated_size(i_size)
+    }
 }
 
 #[cfg(test)]
@@ -69,4 +192,70 @@ mod rotation_tests {
         assert_eq!(Rotation::Down.anticlockwise(), Rotation::Right);
         assert_eq!(Rotation::Left.anticlockwise(), Rotation::Down);
     }
+
+    fn apply(m: [[f32; 2]; 2], p: (f32, f32)) -> (f32, f32) {
+        (m[0][0] * p.0 + m[1][0] * p.1, m[0][1] * p.0 + m[1][1] * p.1)
+    }
+
+    #[test]
+    fn test_exif_orientation_matrices() {
+        // where the stored image's top left and top right corners end up on screen, y up
+        let top_left = (-1.0, -1.0);
+        let top_right = (1.0, -1.0);
+        for (value, expected_left, expected_right) in [
+            (1, (-1.0, 1.0), (1.0, 1.0)),
+            (2, (1.0, 1.0), (-1.0, 1.0)),
+            (3, (1.0, -1.0), (-1.0, -1.0)),
+            (4, (-1.0, -1.0), (1.0, -1.0)),
+            (5, (-1.0, 1.0), (-1.0, -1.0)),
+            (6, (1.0, 1.0), (1.0, -1.0)),
+            (7, (1.0, -1.0), (1.0, 1.0)),
+            (8, (-1.0, -1.0), (-1.0, 1.0)),
+        ] {
+            let m = Orientation::from_exif(value).matrix();
+            assert_eq!(apply(m, top_left), expected_left, "orientation {}", value);
+            assert_eq!(apply(m, top_right), expected_right, "orientation {}", value);
+        }
+    }
+
+    #[test]
+    fn test_unknown_exif_is_upright() {
+        assert_eq!(Orientation::from_exif(0), Orientation::default());
+        assert_eq!(Orientation::from_exif(9), Orientation::default());
+    }
+
+    #[test]
+    fn test_flips_mirror_the_screen() {
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0)];
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value);
+            let before = orientation.matrix();
+            let horizontal = orientation.flip_horizontal().matrix();
+            let vertical = orientation.flip_vertical().matrix();
+            for corner in corners {
+                let (x, y) = apply(before, corner);
+                assert_eq!(apply(horizontal, corner), (-x, y), "orientation {}", value);
+                assert_eq!(apply(vertical, corner), (x, -y), "orientation {}", value);
+            }
+            assert_eq!(orientation.flip_horizontal().flip_horizontal(), orientation);
+            assert_eq!(orientation.flip_vertical().flip_vertical(), orientation);
+        }
+    }
+
+    #[test]
+    fn test_apply_transposes() {
+        // a 2x1 image, red then green, is a 1x2 image with red on top once transposed
+        let image = image::RgbImage::from_raw(2, 1, vec![255, 0, 0, 0, 255, 0]).unwrap();
+        let turned = Orientation::from_exif(5).apply(&DynamicImage::ImageRgb8(image));
+        assert_eq!((turned.width(), turned.height()), (1, 2));
+        assert_eq!(turned.into_bytes(), vec![255, 0, 0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn test_rotated_size() {
+        assert_eq!(Rotation::Up.rotated_size((4, 3)), (4, 3));
+        assert_eq!(Rotation::Right.rotated_size((4, 3)), (3, 4));
+        assert_eq!(Rotation::Down.rotated_size((4, 3)), (4, 3));
+        assert_eq!(Rotation::Left.rotated_size((4, 3)), (3, 4));
+    }
 }
@@ -0,0 +1,192 @@
+use std::time::{Duration, Instant};
+
+/// How often the countdown bar and cross-fades are redrawn
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+/// The height of the countdown bar along the bottom of the window, in pixels
+const BAR_HEIGHT: f32 = 4.0;
+
+/// Slideshow settings kept in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlideshowOptions {
+    /// How long each image is shown for
+    pub interval: Duration,
+    /// How long images cross-fade for, zero to cut straight to the next
+    pub fade: Duration,
+}
+
+impl Default for SlideshowOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            fade: Duration::from_millis(500),
+        }
+    }
+}
+
+/// When to move on to the next image, independent of what is being shown
+#[derive(Debug)]
+pub struct Slideshow {
+    interval: Duration,
+    /// When the next image is due, `None` while paused
+    next_at: Option<Instant>,
+}
+
+impl Slideshow {
+    /// A paused slideshow
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_at: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.next_at.is_some()
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.next_at = Some(now + self.interval);
+    }
+
+    /// Stops the countdown, returning whether it was running
+    pub fn pause(&mut self) -> bool {
+        self.next_at.take().is_some()
+    }
+
+    pub fn toggle(&mut self, now: Instant) {
+        if !self.pause() {
+            self.start(now);
+        }
+    }
+
+    /// Starts the countdown again, as a new image is shown, if running
+    pub fn restart(&mut self, now: Instant) {
+        if self.is_running() {
+            self.start(now);
+        }
+    }
+
+    /// Whether the next image is due at `now`, counting down to the one after if it is
+    pub fn due(&mut self, now: Instant) -> bool {
+        match self.next_at {
+            Some(at) if at <= now => {
+                self.start(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// How much of the interval is left at `now`, from 1 down to 0, if running
+    pub fn remaining(&self, now: Instant) -> Option<f32> {
+        let left = self.next_at?.saturating_duration_since(now);
+        Some((left.as_secs_f32() / self.interval.as_secs_f32()).clamp(0.0, 1.0))
+    }
+
+    /// When the event loop should next wake, to redraw the countdown or move on, if running
+    pub fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        self.next_at.map(|at| at.min(now + FRAME_INTERVAL))
+    }
+}
+
+/// The progress of a cross-fade from the previous image to the current one
+#[derive(Debug)]
+pub struct Fade {
+    started: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    pub fn new(duration: Duration, now: Instant) -> Self {
+        Self {
+            started: now,
+            duration,
+        }
+    }
+
+    /// How far through the fade `now` is, from 0 to 1
+    pub fn progress(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed >= self.duration {
+            1.0
+        } else {
+            elapsed.as_secs_f32() / self.duration.as_secs_f32()
+        }
+    }
+}
+
+/// The transform from the unit quad to the countdown bar, `remaining` of the window wide
+pub fn bar_mat(remaining: f32, d_size: (u32, u32)) -> [[f32; 3]; 3] {
+    let height = BAR_HEIGHT / d_size.1 as f32;
+    [
+        [remaining, 0.0, 0.0],
+        [0.0, height, 0.0],
+        [remaining - 1.0, height - 1.0, 1.0],
+    ]
+}
+
+#[cfg(test)]
+mod slideshow_tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_countdown() {
+        let start = Instant::now();
+        let mut slideshow = Slideshow::new(secs(4));
+        assert!(!slideshow.due(start + secs(10)));
+        assert_eq!(slideshow.remaining(start), None);
+
+        slideshow.start(start);
+        assert_eq!(slideshow.remaining(start + secs(1)), Some(0.75));
+        assert_eq!(slideshow.next_deadline(start), Some(start + FRAME_INTERVAL));
+        assert!(!slideshow.due(start + secs(3)));
+        assert!(slideshow.due(start + secs(5)));
+        // counted from when the image moved on, not when it was due
+        assert!(!slideshow.due(start + secs(8)));
+        assert!(slideshow.due(start + secs(9)));
+
+        slideshow.restart(start + secs(20));
+        assert!(!slideshow.due(start + secs(23)));
+    }
+
+    #[test]
+    fn test_pause() {
+        let start = Instant::now();
+        let mut slideshow = Slideshow::new(secs(4));
+        slideshow.toggle(start);
+        assert!(slideshow.is_running());
+        assert!(slideshow.pause());
+        assert!(!slideshow.pause());
+        assert_eq!(slideshow.next_deadline(start), None);
+        slideshow.restart(start);
+        assert!(!slideshow.is_running());
+        slideshow.toggle(start);
+        assert!(slideshow.due(start + secs(4)));
+    }
+
+    #[test]
+    fn test_fade() {
+        let start = Instant::now();
+        let fade = Fade::new(secs(2), start);
+        assert_eq!(fade.progress(start), 0.0);
+        assert_eq!(fade.progress(start + secs(1)), 0.5);
+        assert_eq!(fade.progress(start + secs(3)), 1.0);
+        assert_eq!(Fade::new(Duration::ZERO, start).progress(start), 1.0);
+    }
+
+    #[test]
+    fn test_bar_spans_the_bottom() {
+        let m = bar_mat(0.25, (800, 400));
+        let apply = |p: (f32, f32)| (m[0][0] * p.0 + m[2][0], m[1][1] * p.1 + m[2][1]);
+        assert_eq!(apply((-1.0, -1.0)), (-1.0, -1.0));
+        assert_eq!(apply((1.0, 1.0)), (-0.5, -1.0 + 2.0 * BAR_HEIGHT / 400.0));
+    }
+}
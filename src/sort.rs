@@ -0,0 +1,347 @@
+use std::{
+    cmp::Ordering,
+    fmt, fs, io,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::{Chars, FromStr},
+    time::SystemTime,
+};
+
+use exif::{In, Tag};
+
+/// The order images in a directory are browsed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    /// Natural order, so `IMG_2` comes before `IMG_10`, ignoring case
+    #[default]
+    Name,
+    /// Oldest modification time first
+    Modified,
+    /// Smallest file first
+    Size,
+    /// Oldest EXIF DateTimeOriginal first, with undated files last
+    ExifDate,
+}
+
+impl SortMode {
+    const ALL: [SortMode; 4] = [
+        SortMode::Name,
+        SortMode::Modified,
+        SortMode::Size,
+        SortMode::ExifDate,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Modified => "mtime",
+            SortMode::Size => "size",
+            SortMode::ExifDate => "exif-date",
+        }
+    }
+
+    /// The mode after this one, wrapping around to the first
+    pub fn next(self) -> Self {
+        let i = SortMode::ALL
+            .iter()
+            .position(|mode| *mode == self)
+            .unwrap_or(0);
+        SortMode::ALL[(i + 1) % SortMode::ALL.len()]
+    }
+}
+
+impl fmt::Display for SortMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SortMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SortMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or(())
+    }
+}
+
+/// Describes an order for the title bar, such as `by mtime, reversed`
+pub fn describe(mode: SortMode, reverse: bool) -> String {
+    if reverse {
+        format!("by {}, reversed", mode)
+    } else {
+        format!("by {}", mode)
+    }
+}
+
+/// Sorts `files` in place, falling back to the path for files that compare equal
+///
+/// Files are kept together with the others in their folder, the folders in name order. With
+/// `reverse` the order within each folder is flipped, undated files coming first for
+/// [`SortMode::ExifDate`].
+pub fn sort_files(files: &mut [PathBuf], mode: SortMode, reverse: bool) {
+    match mode {
+        SortMode::Name => files.sort_by(|a, b| natural_cmp(a, b)),
+        SortMode::Modified => files.sort_by_cached_key(|f| (modified(f), f.clone())),
+        SortMode::Size => files.sort_by_cached_key(|f| (size(f), f.clone())),
+        SortMode::ExifDate => files.sort_by_cached_key(|f| (exif_key(f), f.clone())),
+    }
+    if reverse {
+        files.reverse();
+    }
+    // stable, so each folder keeps the order just given
+    files.sort_by(|a, b| folder_cmp(a, b));
+}
+
+/// Puts `files` in a random order, the same every time for the same files and `seed`
+pub fn shuffle(files: &mut [PathBuf], seed: u64) {
+    let mut rng = XorShift::new(seed);
+    for i in (1..files.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        files.swap(i, j);
+    }
+}
+
+/// xorshift64*, random enough to shuffle with
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // splitmix64, so that nearby seeds give unrelated orders and the state is never zero
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Compares two files the way [`sort_files`] orders them, reading their metadata each time
+pub fn compare(a: &Path, b: &Path, mode: SortMode, reverse: bool) -> Ordering {
+    folder_cmp(a, b).then_with(|| {
+        let ordering = match mode {
+            SortMode::Name => natural_cmp(a, b),
+            SortMode::Modified => (modified(a), a).cmp(&(modified(b), b)),
+            SortMode::Size => (size(a), a).cmp(&(size(b), b)),
+            SortMode::ExifDate => (exif_key(a), a).cmp(&(exif_key(b), b)),
+        };
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    })
+}
+
+/// Compares the folders two files are in by name
+fn folder_cmp(a: &Path, b: &Path) -> Ordering {
+    let (a, b) = (a.parent(), b.parent());
+    natural_cmp(a.unwrap_or(Path::new("")), b.unwrap_or(Path::new("")))
+}
+
+/// Undated files sort after every dated one
+fn exif_key(path: &Path) -> (bool, Option<String>) {
+    let date = exif_date(path);
+    (date.is_none(), date)
+}
+
+/// Compares paths with runs of digits taken as numbers and letters compared ignoring case
+///
+/// Paths that are only told apart by case or leading zeros fall back to comparing bytes.
+fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let (a_text, b_text) = (a.to_string_lossy(), b.to_string_lossy());
+    let (mut a_chars, mut b_chars) = (a_text.chars().peekable(), b_text.chars().peekable());
+    loop {
+        let ordering = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = digit_run(&mut a_chars);
+                let y = digit_run(&mut b_chars);
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                a_chars.next();
+                b_chars.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn digit_run(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+fn size(path: &Path) -> Option<u64> {
+    fs::metadata(path).map(|m| m.len()).ok()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The capture date of a photo, formatted by EXIF so that it sorts as a string
+fn exif_date(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut io::BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+    match &field.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).into_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sort_mode_names() {
+        for mode in SortMode::ALL {
+            assert_eq!(mode.name().parse(), Ok(mode));
+        }
+        assert_eq!("colour".parse::<SortMode>(), Err(()));
+
+        let mut mode = SortMode::default();
+        for _ in SortMode::ALL {
+            mode = mode.next();
+        }
+        assert_eq!(mode, SortMode::default());
+    }
+
+    #[test]
+    fn test_sort_by_name() {
+        let mut files = vec![
+            PathBuf::from("b.png"),
+            PathBuf::from("a.png"),
+            PathBuf::from("C.png"),
+        ];
+        sort_files(&mut files, SortMode::Name, false);
+        assert_eq!(files, ["a.png", "b.png", "C.png"].map(PathBuf::from));
+
+        let mut files = [
+            "IMG_10.jpg",
+            "IMG_2.jpg",
+            "img_1.jpg",
+            "IMG_02.jpg",
+            "IMG_1a.jpg",
+            "IMG_.jpg",
+        ]
+        .map(PathBuf::from);
+        sort_files(&mut files, SortMode::Name, false);
+        let expected = [
+            "IMG_.jpg",
+            "img_1.jpg",
+            "IMG_1a.jpg",
+            "IMG_02.jpg",
+            "IMG_2.jpg",
+            "IMG_10.jpg",
+        ];
+        assert_eq!(files, expected.map(PathBuf::from));
+
+        sort_files(&mut files, SortMode::Name, true);
+        assert_eq!(files[0], PathBuf::from("IMG_10.jpg"));
+    }
+
+    #[test]
+    fn test_sort_by_modified() {
+        let dir = TestDir::new("sort");
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        for (name, age) in [("a.png", 10), ("b.png", 30), ("c.png", 20)] {
+            let path = dir.join(name);
+            let file = fs::File::create(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(age)).unwrap();
+            files.push(path);
+        }
+
+        sort_files(&mut files, SortMode::Modified, false);
+        assert_eq!(files, ["b.png", "c.png", "a.png"].map(|f| dir.join(f)));
+    }
+
+    #[test]
+    fn test_folders_stay_together() {
+        let mut files = [
+            "shoot/day10/a.jpg",
+            "shoot/z.jpg",
+            "shoot/day2/b.jpg",
+            "shoot/day2/a.jpg",
+            "shoot/a.jpg",
+        ]
+        .map(PathBuf::from);
+        sort_files(&mut files, SortMode::Name, true);
+        let expected = [
+            "shoot/z.jpg",
+            "shoot/a.jpg",
+            "shoot/day2/b.jpg",
+            "shoot/day2/a.jpg",
+            "shoot/day10/a.jpg",
+        ];
+        assert_eq!(files, expected.map(PathBuf::from));
+        for pair in files.windows(2) {
+            assert!(compare(&pair[0], &pair[1], SortMode::Name, true).is_lt());
+        }
+    }
+
+    #[test]
+    fn test_shuffle_is_stable() {
+        let files: Vec<PathBuf> = (0..20)
+            .map(|i| PathBuf::from(format!("{}.png", i)))
+            .collect();
+        let shuffled = |seed| {
+            let mut files = files.clone();
+            shuffle(&mut files, seed);
+            files
+        };
+        assert_eq!(shuffled(42), shuffled(42));
+        assert_ne!(shuffled(42), shuffled(43));
+        assert_ne!(shuffled(0), files);
+
+        let mut sorted = shuffled(42);
+        sorted.sort();
+        let mut expected = files.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn test_sort_by_size() {
+        let dir = TestDir::new("sort_size");
+        let mut files = Vec::new();
+        for (name, len) in [("a.png", 30), ("b.png", 10), ("c.png", 20)] {
+            let path = dir.join(name);
+            fs::write(&path, vec![0; len]).unwrap();
+            files.push(path);
+        }
+
+        sort_files(&mut files, SortMode::Size, false);
+        assert_eq!(files, ["b.png", "c.png", "a.png"].map(|f| dir.join(f)));
+        sort_files(&mut files, SortMode::Size, true);
+        assert_eq!(files, ["a.png", "c.png", "b.png"].map(|f| dir.join(f)));
+    }
+}
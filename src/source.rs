@@ -0,0 +1,230 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace, warn};
+
+use crate::{
+    format,
+    sort::{self, SortMode},
+};
+
+/// Where the images being browsed come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// The images in a directory
+    Folder(PathBuf),
+    /// The images in a directory and all of its subdirectories
+    Recursive(PathBuf),
+    /// Images named one by one, browsed in the order given
+    List(Vec<PathBuf>),
+}
+
+impl Source {
+    /// The directory browsed, if it is one, which is watched for changes
+    pub fn directory(&self) -> Option<&Path> {
+        match self {
+            Source::Folder(dir) | Source::Recursive(dir) => Some(dir),
+            Source::List(_) => None,
+        }
+    }
+
+    pub fn is_recursive(&self) -> bool {
+        matches!(self, Source::Recursive(_))
+    }
+
+    /// The images to browse, in `mode` order unless they are a list
+    pub fn files(&self, mode: SortMode, reverse: bool) -> Result<Vec<PathBuf>, io::Error> {
+        let dir = match self {
+            Source::Folder(dir) | Source::Recursive(dir) => dir,
+            Source::List(files) => return Ok(files.clone()),
+        };
+        let mut files = list_dir(dir, self.is_recursive())?;
+        files.retain(|f| format::is_browsable(f));
+        sort::sort_files(&mut files, mode, reverse);
+        Ok(files)
+    }
+
+    /// `path` named the way [`Source::files`] names it, if it is in the browsed directory
+    ///
+    /// Lists never change, so nothing is ever in one.
+    pub fn listing_path(&self, path: &Path) -> Option<PathBuf> {
+        let directory = self.directory()?;
+        let relative = match path.strip_prefix(directory) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => {
+                // the parent is compared as it may be named differently, the file may be gone
+                let parent = path.parent()?.canonicalize().ok()?;
+                let relative = parent.strip_prefix(directory.canonicalize().ok()?).ok()?;
+                relative.join(path.file_name()?)
+            }
+        };
+        // files in subdirectories are only listed when recursive, and never in hidden ones
+        let folders = relative.parent().unwrap_or(Path::new(""));
+        if folders != Path::new("") && (!self.is_recursive() || has_hidden_folder(folders)) {
+            return None;
+        }
+        Some(directory.join(relative))
+    }
+
+    /// The name to show for `path`, with the folders it is in when recursive
+    pub fn name(&self, path: &Path) -> String {
+        let relative = match self {
+            Source::Recursive(dir) => path.strip_prefix(dir).ok(),
+            _ => None,
+        };
+        relative
+            .or_else(|| path.file_name().map(Path::new))
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Builds a list from paths given one by one, each directory giving its images in `mode` order
+pub fn expand(
+    paths: &[PathBuf],
+    recursive: bool,
+    mode: SortMode,
+    reverse: bool,
+) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let dir = if recursive {
+                Source::Recursive(path.clone())
+            } else {
+                Source::Folder(path.clone())
+            };
+            files.append(&mut dir.files(mode, reverse)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Reads the paths in a list file, or on stdin for `-`
+///
+/// Each line names one image or directory. Blank lines and lines starting with `#`, such as the
+/// `#EXTINF` lines of an `.m3u` playlist, are skipped. Relative paths are taken from the
+/// directory of the list file, and paths that do not exist are left out.
+pub fn read_list(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    if path == Path::new("-") {
+        return parse_list(io::stdin().lock(), Path::new(""));
+    }
+    let file = fs::File::open(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{:?}: {}", path, err)))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    parse_list(io::BufReader::new(file), base)
+}
+
+fn parse_list<R: BufRead>(reader: R, base: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = base.join(line);
+        if path.exists() {
+            paths.push(path);
+        } else {
+            warn!("{:?} in the list does not exist", path);
+        }
+    }
+    Ok(paths)
+}
+
+/// The files in `dir`, and in its subdirectories if `recursive`
+///
+/// Hidden subdirectories are skipped. Symlinked directories are followed, but no directory is
+/// listed twice, so links back up the tree cannot loop.
+pub fn list_dir(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = Vec::new();
+    let mut listed = HashSet::new();
+    if recursive {
+        listed.insert(dir.canonicalize()?);
+    }
+    list_into(dir, recursive, &mut listed, &mut files)?;
+    Ok(files)
+}
+
+fn list_into(
+    dir: &Path,
+    recursive: bool,
+    listed: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !recursive || !path.is_dir() {
+            files.push(path);
+        } else if is_hidden(&path) {
+            trace!("skipping hidden {:?}", path);
+        } else {
+            match path.canonicalize() {
+                Ok(canonical) => {
+                    // a link to a directory already listed, such as one further up the tree
+                    if !listed.insert(canonical) {
+                        debug!("{:?} is already listed", path);
+                    } else if let Err(err) = list_into(&path, true, listed, files) {
+                        warn!("{:?}: {:?}", path, err);
+                    }
+                }
+                Err(err) => warn!("{:?}: {:?}", path, err),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn has_hidden_folder(path: &Path) -> bool {
+    path.components()
+        .any(|folder| folder.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+#[cfg(test)]
+mod source_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_parse_list() {
+        let list = "\u{feff}#EXTM3U\n#EXTINF:-1,dice\ndice.png\r\n\n  0.jpg  \nmissing.png\n/\n";
+        let paths = parse_list(list.as_bytes(), Path::new("./test_images")).unwrap();
+        assert_eq!(
+            paths,
+            ["./test_images/dice.png", "./test_images/0.jpg", "/"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_read_list() {
+        let dir = TestDir::new("source");
+        let path = dir.join("list.m3u");
+        let dice = Path::new("./test_images/dice.png").canonicalize().unwrap();
+        fs::write(&path, format!("{}\n", dice.display())).unwrap();
+        assert_eq!(read_list(&path).unwrap(), vec![dice]);
+        fs::remove_file(&path).unwrap();
+        assert!(read_list(&path).is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        let path = Path::new("shoot/day2/a.jpg");
+        assert_eq!(Source::Folder("shoot/day2".into()).name(path), "a.jpg");
+        assert_eq!(Source::Recursive("shoot".into()).name(path), "day2/a.jpg");
+        assert_eq!(Source::List(vec![path.into()]).name(path), "a.jpg");
+        assert_eq!(Source::List(Vec::new()).listing_path(path), None);
+    }
+}
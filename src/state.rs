@@ -1,16 +1,30 @@
 use std::{
-    fs::{self, DirEntry},
-    io,
-    path::Path,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::rotation::Rotation;
-use exif::Tag;
+use crate::{
+    cli::Options,
+    format,
+    image_saving::EncodeOptions,
+    index::FileIndex,
+    metadata::Strip,
+    rotation::Orientation,
+    sort::{self, SortMode},
+    source::{self, Source},
+    view::View,
+};
 use glium::glutin::event::ModifiersState;
 use log::{info, trace, warn};
 
+/// How many images [`State::back_img`] can go back through
+const HISTORY_LEN: usize = 100;
+
 pub struct State {
-    pub rotation: Rotation,
+    pub orientation: Orientation,
+    pub view: View,
+    /// Where the save dialog opens, the directory of the image opened first
     pub directory: String,
     pub image_uri: String,
     pub image_changed: bool,
@@ -19,12 +33,43 @@ pub struct State {
     pub drag_origin: Option<(u32, u32)>,
     pub running: bool,
     pub needs_redraw: bool,
+    pub sort: SortMode,
+    /// Browse in the opposite of the sort order
+    pub reverse: bool,
+    pub source: Source,
+    /// What is being browsed, built when it is opened
+    pub index: FileIndex,
+    /// Digits typed after `G`, the number of an image to jump to
+    pub jump: Option<String>,
+    /// Where a save is waiting on `Y` to go ahead, as it would replace the image being shown
+    pub overwrite: Option<PathBuf>,
+    /// Open each image added to the directory, for tethered shooting
+    pub follow: bool,
+    /// A file created while following and its size when last looked at, opened once it stops
+    /// growing
+    pub arriving: Option<(PathBuf, u64)>,
+    /// Carry on from the other end when stepping past the first or last image
+    pub wrap: bool,
+    /// Browse in the random order given by `seed` rather than the sort order
+    pub shuffle: bool,
+    pub seed: u64,
+    /// Images opened before the current one, most recent last
+    history: Vec<PathBuf>,
+    pub slideshow: Option<Duration>,
+    /// Metadata left out of saved images
+    pub strip: Strip,
+    pub encode: EncodeOptions,
+    /// Keep a `.bak` of each file that is saved over
+    pub backup: bool,
+    /// The outcome of the last action, shown in the title until the image changes
+    pub status: Option<String>,
 }
 
 impl State {
     pub fn default() -> Self {
         Self {
-            rotation: Rotation::Up,
+            orientation: Orientation::default(),
+            view: View::default(),
             directory: String::from("./img/"),
             image_uri: String::from("./img/no_image.png"),
             image_changed: false,
@@ -33,93 +78,615 @@ impl State {
             drag_origin: None,
             running: true,
             needs_redraw: true,
+            sort: SortMode::default(),
+            reverse: false,
+            source: Source::Folder(PathBuf::from("./img/")),
+            index: FileIndex::default(),
+            jump: None,
+            overwrite: None,
+            follow: false,
+            arriving: None,
+            wrap: false,
+            shuffle: false,
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64),
+            history: Vec::new(),
+            slideshow: None,
+            strip: Strip::default(),
+            encode: EncodeOptions::default(),
+            backup: false,
+            status: None,
         }
     }
 
-    fn get_dir_cont(&self) -> Result<Vec<fs::DirEntry>, io::Error> {
-        let files = fs::read_dir(Path::new(&self.directory))?;
-        files.collect::<Result<Vec<fs::DirEntry>, io::Error>>()
+    /// Sets up what to browse from the command line options
+    pub fn open_options(&mut self, options: &Options) -> Result<(), io::Error> {
+        self.sort = options.sort;
+        self.reverse = options.reverse;
+        self.follow = options.follow;
+        if let Some(seed) = options.shuffle {
+            self.shuffle = true;
+            self.seed = seed;
+        }
+        self.slideshow = options.slideshow;
+        self.strip = options.strip;
+
+        let mut first = options.start_at.clone();
+        let mut paths = options.paths.clone();
+        for list in &options.lists {
+            paths.append(&mut source::read_list(list)?);
+        }
+        let listed = !options.lists.is_empty();
+        if listed && paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no images listed",
+            ));
+        }
+        // with nothing else to browse, the image to start at is browsed like one given as a path
+        if paths.is_empty() {
+            paths.extend(options.start_at.clone());
+        }
+        match paths.as_slice() {
+            [] => (),
+            [dir] if !listed && dir.is_dir() => {
+                self.directory = path_string(dir);
+                self.source = folder(dir.clone(), options.recursive);
+            }
+            [file] if !listed => {
+                let parent = match file.parent() {
+                    Some(parent) if parent != Path::new("") => parent,
+                    _ => Path::new("."),
+                };
+                self.directory = path_string(parent);
+                self.source = folder(parent.to_path_buf(), options.recursive);
+                // keeps the path comparable with the directory listing
+                first = first.or_else(|| file.file_name().map(|name| parent.join(name)));
+            }
+            paths => {
+                let files = source::expand(paths, options.recursive, self.sort, self.reverse)?;
+                self.source = Source::List(files);
+            }
+        }
+
+        match self.build_index() {
+            Ok(()) => (),
+            // nothing was asked for, so without the default folder there is just nothing to browse
+            Err(err) if paths.is_empty() => {
+                warn!("{:?}: {}", self.source.directory(), err);
+                self.index = FileIndex::default();
+            }
+            Err(err) => return Err(err),
+        }
+        let first = first.map(|first| self.indexed_path(&first));
+        if let Some(first) = first.or_else(|| self.index.first().map(Path::to_path_buf)) {
+            if let Source::List(_) = self.source {
+                if let Some(parent) = first.parent() {
+                    self.directory = path_string(parent);
+                }
+            }
+            self.image_uri = path_string(&first);
+            self.index.select(&first);
+        }
+        Ok(())
     }
 
-    pub fn load_img(&mut self) {
-        let file = match fs::File::open(Path::new(&self.image_uri)) {
-            Ok(f) => f,
-            Err(err) => {
-                warn!("{:?}", err);
-                return;
+    /// `path` named the way the index names it, so that it matches however it was written
+    fn indexed_path(&self, path: &Path) -> PathBuf {
+        if let Some(listed) = self.source.listing_path(path) {
+            return listed;
+        }
+        if let (Source::List(files), Ok(path)) = (&self.source, path.canonicalize()) {
+            if let Some(file) = files
+                .iter()
+                .find(|f| f.canonicalize().is_ok_and(|f| f == path))
+            {
+                return file.clone();
+            }
+        }
+        path.to_path_buf()
+    }
+
+    /// Lists what is being browsed into [`State::index`]
+    fn build_index(&mut self) -> Result<(), io::Error> {
+        self.index = FileIndex::new(self.source.files(self.sort, self.reverse)?);
+        if self.shuffle {
+            self.index.shuffle(self.seed);
+        }
+        self.index.select(Path::new(&self.image_uri));
+        Ok(())
+    }
+
+    /// Changes the sort order, ending any shuffle and keeping the current image
+    ///
+    /// Images given as a list stay in the order they were given.
+    pub fn set_sort(&mut self, mode: SortMode, reverse: bool) {
+        self.sort = mode;
+        self.reverse = reverse;
+        self.shuffle = false;
+        self.reorder();
+    }
+
+    /// Starts or ends browsing in a random order, keeping the current image
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+        self.reorder();
+    }
+
+    fn reorder(&mut self) {
+        match &self.source {
+            Source::List(files) => {
+                self.index = FileIndex::new(files.clone());
+                self.index.select(Path::new(&self.image_uri));
+            }
+            _ => self.index.sort(self.sort, self.reverse),
+        }
+        if self.shuffle {
+            self.index.shuffle(self.seed);
+        }
+    }
+
+    /// Describes the browsing order for the title bar
+    pub fn order_text(&self) -> String {
+        if self.shuffle {
+            format!("shuffled, seed {}", self.seed)
+        } else {
+            sort::describe(self.sort, self.reverse)
+        }
+    }
+
+    /// Adds a file that appeared while browsing, if it belongs in the browsed directory
+    ///
+    /// When following, the new file is opened.
+    pub fn add_file(&mut self, path: &Path) {
+        let path = match self.source.listing_path(path) {
+            Some(path) if format::is_browsable(&path) => path,
+            _ => return,
+        };
+        self.index.insert(path.clone(), self.sort, self.reverse);
+        if self.follow {
+            self.follow_to(path);
+        }
+    }
+
+    /// Adds a file that was just created, which may not have been written yet
+    ///
+    /// When following, it is opened once it is written or has stopped growing, see
+    /// [`State::settle_arriving`].
+    pub fn file_created(&mut self, path: &Path) {
+        let path = match self.source.listing_path(path) {
+            Some(path) if format::is_browsable(&path) => path,
+            _ => return,
+        };
+        self.index.insert(path.clone(), self.sort, self.reverse);
+        if self.follow {
+            if let Ok(metadata) = fs::metadata(&path) {
+                self.arriving = Some((path, metadata.len()));
             }
+        }
+    }
+
+    /// Opens the file arriving while following if its size has not changed since last time,
+    /// returning whether it is still to be waited for
+    ///
+    /// Not every platform says when a file has been written, so this is called every
+    /// [`watch::SETTLE_INTERVAL`](crate::watch::SETTLE_INTERVAL) while a file is arriving.
+    pub fn settle_arriving(&mut self) -> bool {
+        let (path, last_len) = match self.arriving.take() {
+            Some(arriving) if self.follow => arriving,
+            _ => return false,
         };
-        let mut buf_reader = io::BufReader::new(&file);
-        let exif_reader = exif::Reader::new();
-        match exif_reader.read_from_container(&mut buf_reader) {
-            Ok(exif) => {
-                if let Some(orient) = exif.fields().find(|f| f.tag == Tag::Orientation) {
-                    match orient.value.get_uint(0) {
-                        Some(1u32) => self.rotation = Rotation::Up,
-                        Some(6u32) => self.rotation = Rotation::Right,
-                        Some(3u32) => self.rotation = Rotation::Down,
-                        Some(8u32) => self.rotation = Rotation::Left,
-                        _ => self.rotation = Rotation::Up,
-                    }
-                }
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.len() > 0 && metadata.len() == last_len => {
+                self.follow_to(path);
+                false
+            }
+            Ok(metadata) => {
+                self.arriving = Some((path, metadata.len()));
+                true
             }
+            // deleted before it was finished
+            Err(_) => false,
+        }
+    }
+
+    /// Opens a file that appeared while following, in place of any still arriving
+    fn follow_to(&mut self, path: PathBuf) {
+        self.arriving = None;
+        if path != Path::new(&self.image_uri) {
+            self.index.select(&path);
+            self.open_img(path);
+        }
+    }
+
+    /// Drops a file that has gone from the browsed directory
+    ///
+    /// If it was the current image, the image that took its place is opened, or the one before
+    /// it when it was last.
+    pub fn remove_file(&mut self, path: &Path) {
+        let path = match self.source.listing_path(path) {
+            Some(path) => path,
+            None => return,
+        };
+        let position = self.index.position();
+        if !self.index.remove(&path) || path != Path::new(&self.image_uri) {
+            return;
+        }
+        let next = position
+            .unwrap_or(0)
+            .min(self.index.len().saturating_sub(1));
+        match self.index.go_to(next).map(Path::to_path_buf) {
+            Some(next) => self.open_img(next),
+            None => self.status = Some(String::from("deleted")),
+        }
+    }
+
+    /// Rereads a file that finished being written, if it is shown or being followed
+    pub fn file_written(&mut self, path: &Path) {
+        let path = match self.source.listing_path(path) {
+            Some(path) => path,
+            None => return,
+        };
+        if path == Path::new(&self.image_uri) {
+            self.load_img();
+        } else if self.follow {
+            self.add_file(&path);
+        }
+    }
+
+    /// The current image's name for the title, with the folders it is in when recursive
+    pub fn title_name(&self) -> String {
+        self.source.name(Path::new(&self.image_uri))
+    }
+
+    /// Where the current image is in the index, as `N of M`
+    pub fn position_text(&self) -> Option<String> {
+        let position = self.index.position()?;
+        Some(format!("{} of {}", position + 1, self.index.len()))
+    }
+
+    pub fn load_img(&mut self) {
+        match Orientation::read(Path::new(&self.image_uri)) {
+            Ok(orientation) => self.orientation = orientation,
             Err(err) => {
-                self.rotation = Rotation::Up;
+                self.orientation = Orientation::default();
                 warn!("exif: {:?}", err);
             }
         };
 
+        self.view.fit();
+        self.status = None;
+        // what was asked about is no longer what is shown
+        self.overwrite = None;
         self.image_changed = true;
         self.needs_redraw = true;
     }
 
-    fn open_img<I: Iterator<Item = DirEntry>>(&mut self, mut i: I) {
-        if let Some(new_image) = i.find(|f| match f.path().as_path().extension() {
-            Some(ext) => match ext.to_ascii_lowercase().to_str() {
-                Some(extension) => ["png", "jpg", "qoi", "ico", "jfif"].contains(&extension),
-                None => false,
-            },
-            None => false,
-        }) {
-            self.image_uri = new_image.path().as_path().to_str().unwrap().to_string();
-            info!("Opening: {:?}", self.image_uri);
-            self.load_img();
-        };
+    /// Opens `path`, remembering the current image to go back to
+    fn open_img(&mut self, path: PathBuf) {
+        let previous = PathBuf::from(&self.image_uri);
+        if previous != path {
+            if self.history.len() == HISTORY_LEN {
+                self.history.remove(0);
+            }
+            self.history.push(previous);
+        }
+        self.show_img(path);
     }
 
-    pub fn next_img(&mut self) {
-        if self.image_changed || !self.running {}
-        trace!("next image");
-
-        match self.get_dir_cont() {
-            Ok(mut files) => {
-                files.sort_by(|a, b| a.path().partial_cmp(&b.path()).unwrap());
-                let mut i = files.into_iter();
+    fn show_img(&mut self, path: PathBuf) {
+        self.image_uri = path_string(&path);
+        info!("Opening: {:?}", self.image_uri);
+        self.load_img();
+    }
 
-                i.find(|f| f.path() == Path::new(&self.image_uri));
-                self.open_img(i);
-            }
-            Err(err) => {
-                warn!("{:?}", err);
+    /// Reopens the image shown before the current one, skipping any no longer browsed
+    pub fn back_img(&mut self) {
+        trace!("back");
+        while let Some(path) = self.history.pop() {
+            if self.index.select(&path) {
+                self.show_img(path);
+                return;
             }
         }
     }
 
+    /// Up to `distance` browsable images either side of the current one, nearest first
+    pub fn neighbours(&self, distance: usize) -> Vec<PathBuf> {
+        self.index.neighbours(distance)
+    }
+
+    pub fn next_img(&mut self) {
+        trace!("next image");
+        if let Some(next) = self.index.step(1, self.wrap).map(Path::to_path_buf) {
+            self.open_img(next);
+        }
+    }
+
     pub fn prev_img(&mut self) {
-        if self.image_changed || !self.running {}
         trace!("prev image");
+        if let Some(prev) = self.index.step(-1, self.wrap).map(Path::to_path_buf) {
+            self.open_img(prev);
+        }
+    }
 
-        match self.get_dir_cont() {
-            Ok(mut files) => {
-                files.sort_by(|a, b| a.path().partial_cmp(&b.path()).unwrap());
-                let mut i = files.into_iter().rev();
+    pub fn first_img(&mut self) {
+        self.go_to_img(0);
+    }
 
-                i.find(|f| f.path() == Path::new(&self.image_uri));
-                self.open_img(i);
-            }
-            Err(err) => {
-                warn!("{:?}", err);
+    pub fn last_img(&mut self) {
+        if let Some(last) = self.index.len().checked_sub(1) {
+            self.go_to_img(last);
+        }
+    }
+
+    /// Opens the image at `index`, counting from 0, if there is one
+    pub fn go_to_img(&mut self, index: usize) {
+        trace!("image {}", index);
+        if let Some(path) = self.index.go_to(index).map(Path::to_path_buf) {
+            if path != Path::new(&self.image_uri) {
+                self.open_img(path);
             }
         }
     }
 }
+
+fn folder(dir: PathBuf, recursive: bool) -> Source {
+    if recursive {
+        Source::Recursive(dir)
+    } else {
+        Source::Folder(dir)
+    }
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod state_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    fn options(paths: &[&str]) -> Options {
+        Options {
+            paths: paths.iter().map(PathBuf::from).collect(),
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_open_directory() {
+        let mut state = State::default();
+        state.open_options(&options(&["./test_images"])).unwrap();
+        assert_eq!(state.image_uri, "./test_images/0.jpg");
+        state.next_img();
+        assert_eq!(state.image_uri, "./test_images/0.png");
+        state.prev_img();
+        assert_eq!(state.image_uri, "./test_images/0.jpg");
+        // the start of the folder does not wrap around
+        state.prev_img();
+        assert_eq!(state.image_uri, "./test_images/0.jpg");
+        assert_eq!(
+            state.position_text().unwrap(),
+            format!("1 of {}", state.index.len())
+        );
+
+        state.last_img();
+        assert_eq!(state.image_uri, "./test_images/wikipedia_008.qoi");
+        state.next_img();
+        assert_eq!(state.image_uri, "./test_images/wikipedia_008.qoi");
+        state.go_to_img(2);
+        assert_eq!(state.image_uri, "./test_images/0.qoi");
+        state.first_img();
+        assert_eq!(
+            state.position_text().unwrap(),
+            format!("1 of {}", state.index.len())
+        );
+    }
+
+    #[test]
+    fn test_open_file_browses_its_directory() {
+        let mut state = State::default();
+        state
+            .open_options(&options(&["./test_images/dice.png"]))
+            .unwrap();
+        assert_eq!(state.directory, "./test_images");
+        state.next_img();
+        assert_eq!(state.image_uri, "./test_images/dice.qoi");
+    }
+
+    #[test]
+    fn test_start_at_however_written() {
+        let mut state = State::default();
+        let mut opened = options(&["./test_images"]);
+        opened.start_at = Some(PathBuf::from("test_images/dice.png"));
+        state.open_options(&opened).unwrap();
+        assert_eq!(state.image_uri, "./test_images/dice.png");
+        assert!(state.position_text().is_some());
+        state.next_img();
+        assert_eq!(state.image_uri, "./test_images/dice.qoi");
+
+        // with no path, the folder of the image to start at is browsed
+        let mut state = State::default();
+        let mut opened = options(&[]);
+        opened.start_at = Some(PathBuf::from("test_images/dice.png"));
+        state.open_options(&opened).unwrap();
+        assert_eq!(state.directory, "test_images");
+        assert_eq!(state.image_uri, "test_images/dice.png");
+        state.next_img();
+        assert_eq!(state.image_uri, "test_images/dice.qoi");
+
+        let mut state = State::default();
+        let mut opened = options(&["./test_images/dice.qoi", "./img/no_image.png"]);
+        opened.start_at = Some(PathBuf::from("img/../img/no_image.png"));
+        state.open_options(&opened).unwrap();
+        assert_eq!(state.image_uri, "./img/no_image.png");
+        assert_eq!(state.position_text().unwrap(), "2 of 2");
+    }
+
+    #[test]
+    fn test_wrap_shuffle_and_back() {
+        let mut state = State::default();
+        state.open_options(&options(&["./test_images"])).unwrap();
+        state.wrap = true;
+        state.prev_img();
+        assert_eq!(state.image_uri, "./test_images/wikipedia_008.qoi");
+        state.next_img();
+        assert_eq!(state.image_uri, "./test_images/0.jpg");
+
+        state.seed = 42;
+        state.set_shuffle(true);
+        assert_eq!(state.image_uri, "./test_images/0.jpg");
+        let mut visited = vec![state.image_uri.clone()];
+        for _ in 0..3 {
+            state.next_img();
+            visited.push(state.image_uri.clone());
+        }
+        for expected in visited.iter().rev().skip(1) {
+            state.back_img();
+            assert_eq!(&state.image_uri, expected);
+        }
+
+        // the same seed gives the same order
+        let mut again = State::default();
+        let mut options = options(&["./test_images"]);
+        options.shuffle = Some(42);
+        options.start_at = Some(PathBuf::from("./test_images/0.jpg"));
+        again.open_options(&options).unwrap();
+        for expected in &visited[1..] {
+            again.next_img();
+            assert_eq!(&again.image_uri, expected);
+        }
+
+        state.set_sort(SortMode::Name, false);
+        assert!(!state.shuffle);
+        state.next_img();
+        assert_eq!(state.image_uri, "./test_images/0.png");
+    }
+
+    #[test]
+    fn test_directory_changes() {
+        let dir = TestDir::new("state_changes");
+        for name in ["IMG_1.png", "IMG_3.png"] {
+            fs::copy("./test_images/dice.png", dir.join(name)).unwrap();
+        }
+        let mut state = State::default();
+        state
+            .open_options(&options(&[dir.to_str().unwrap()]))
+            .unwrap();
+        assert_eq!(state.position_text().unwrap(), "1 of 2");
+
+        fs::copy("./test_images/dice.png", dir.join("IMG_2.png")).unwrap();
+        state.add_file(&dir.canonicalize().unwrap().join("IMG_2.png"));
+        state.add_file(&dir.join("notes.txt"));
+        assert_eq!(state.position_text().unwrap(), "1 of 3");
+
+        state.follow = true;
+        fs::copy("./test_images/dice.png", dir.join("IMG_10.png")).unwrap();
+        state.add_file(&dir.join("IMG_10.png"));
+        assert_eq!(state.image_uri, path_string(&dir.join("IMG_10.png")));
+
+        // the last image is deleted, so the one before it is shown
+        fs::remove_file(dir.join("IMG_10.png")).unwrap();
+        state.remove_file(&dir.join("IMG_10.png"));
+        assert_eq!(state.image_uri, path_string(&dir.join("IMG_3.png")));
+        assert_eq!(state.position_text().unwrap(), "3 of 3");
+
+        // a created file is only opened once it has stopped growing
+        let path = dir.join("IMG_11.png");
+        fs::write(&path, &fs::read("./test_images/dice.png").unwrap()[..100]).unwrap();
+        state.file_created(&path);
+        assert_eq!(state.position_text().unwrap(), "3 of 4");
+        fs::copy("./test_images/dice.png", &path).unwrap();
+        assert!(state.settle_arriving());
+        assert_eq!(state.image_uri, path_string(&dir.join("IMG_3.png")));
+        assert!(!state.settle_arriving());
+        assert_eq!(state.image_uri, path_string(&path));
+    }
+
+    #[test]
+    fn test_recursive() {
+        let dir = TestDir::new("state_recursive");
+        for folder in ["day2", "day10", ".thumbnails"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+        }
+        for file in ["b.png", "day2/a.png", "day10/a.png", ".thumbnails/a.png"] {
+            fs::copy("./test_images/dice.png", dir.join(file)).unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("day2/loop")).unwrap();
+
+        let mut state = State::default();
+        let mut options = options(&[dir.to_str().unwrap()]);
+        options.recursive = true;
+        options.sort = SortMode::Name;
+        state.open_options(&options).unwrap();
+        let mut seen = vec![state.title_name()];
+        for _ in 0..3 {
+            state.next_img();
+            seen.push(state.title_name());
+        }
+        let expected = ["b.png", "day2/a.png", "day10/a.png", "day10/a.png"];
+        assert_eq!(seen, expected.map(|name| path_string(Path::new(name))));
+
+        // hidden folders are left out of changes too
+        state.add_file(&dir.join(".thumbnails/a.png"));
+        assert_eq!(state.index.len(), 3);
+    }
+
+    #[test]
+    fn test_list_file() {
+        let dir = TestDir::new("state_list");
+        let list = dir.join("list.m3u");
+        let images = Path::new("./test_images").canonicalize().unwrap();
+        let text = format!("#EXTM3U\n{0}/dice.qoi\n{0}/0.jpg\n", images.display());
+        fs::write(&list, text).unwrap();
+
+        let mut state = State::default();
+        let mut options = options(&[]);
+        options.lists = vec![list.clone()];
+        state.open_options(&options).unwrap();
+        assert_eq!(state.image_uri, path_string(&images.join("dice.qoi")));
+        state.next_img();
+        assert_eq!(state.image_uri, path_string(&images.join("0.jpg")));
+        assert_eq!(state.position_text().unwrap(), "2 of 2");
+        assert_eq!(state.source.directory(), None);
+
+        fs::write(&list, "# nothing\n").unwrap();
+        assert!(State::default().open_options(&options).is_err());
+    }
+
+    #[test]
+    fn test_missing_folder() {
+        let dir = TestDir::new("state_missing");
+        let mut state = State::default();
+        state.source = Source::Folder(dir.join("img"));
+        state.open_options(&options(&[])).unwrap();
+        assert_eq!(state.image_uri, State::default().image_uri);
+        assert_eq!(state.position_text(), None);
+
+        let missing = dir.join("missing").join("a.png");
+        let mut state = State::default();
+        assert!(state
+            .open_options(&options(&[missing.to_str().unwrap()]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_explicit_files() {
+        let mut state = State::default();
+        let mut options = options(&["./test_images/dice.qoi", "./img/no_image.png"]);
+        options.start_at = Some(PathBuf::from("./img/no_image.png"));
+        state.open_options(&options).unwrap();
+        assert_eq!(state.image_uri, "./img/no_image.png");
+        state.prev_img();
+        assert_eq!(state.image_uri, "./test_images/dice.qoi");
+        assert_eq!(
+            state.neighbours(2),
+            vec![PathBuf::from("./img/no_image.png")]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// An empty scratch directory for a test, removed with everything in it when dropped
+///
+/// The name includes the process id and a counter, so tests running at the same time, in this
+/// run or another, never share one.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!("femtophotos_{}_{}_{}", name, process::id(), count));
+        // left over by an earlier run whose process id has been reused
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod test_dir_tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_and_removed() {
+        let a = TestDir::new("helper");
+        let b = TestDir::new("helper");
+        assert_ne!(a.to_path_buf(), b.to_path_buf());
+        fs::write(a.join("file"), b"data").unwrap();
+        let path = a.to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
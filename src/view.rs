@@ -0,0 +1,220 @@
+use crate::rotation::Orientation;
+
+/// Zoom applied per key press or mouse wheel notch
+pub const ZOOM_STEP: f32 = 1.25;
+const MIN_SCALE: f32 = 0.01;
+const MAX_SCALE: f32 = 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    /// Scale the image to fit the window, following window resizes
+    Fit,
+    /// A fixed number of screen pixels per image pixel
+    Scale(f32),
+}
+
+/// Zoom and pan of the image within the window
+///
+/// Positions are in screen pixels relative to the centre of the window, with y pointing up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub zoom: Zoom,
+    pub pan: (f32, f32),
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            zoom: Zoom::Fit,
+            pan: (0.0, 0.0),
+        }
+    }
+}
+
+impl View {
+    pub fn is_fit(&self) -> bool {
+        self.zoom == Zoom::Fit
+    }
+
+    /// Screen pixels per image pixel
+    pub fn scale(&self, orientation: &Orientation, d_size: (u32, u32), i_size: (u32, u32)) -> f32 {
+        match self.zoom {
+            Zoom::Fit => fit_scale(orientation, d_size, i_size),
+            Zoom::Scale(scale) => scale,
+        }
+    }
+
+    pub fn fit(&mut self) {
+        *self = View::default();
+    }
+
+    pub fn actual_pixels(&mut self) {
+        self.zoom = Zoom::Scale(1.0);
+        self.pan = (0.0, 0.0);
+    }
+
+    /// Zooms by `factor` keeping the image point under `cursor` where it is
+    pub fn zoom_at(
+        &mut self,
+        factor: f32,
+        cursor: (f32, f32),
+        orientation: &Orientation,
+        d_size: (u32, u32),
+        i_size: (u32, u32),
+    ) {
+        let old = self.scale(orientation, d_size, i_size);
+        let new = (old * factor).clamp(MIN_SCALE, MAX_SCALE);
+        let ratio = new / old;
+        self.pan = (
+            cursor.0 - ratio * (cursor.0 - self.pan.0),
+            cursor.1 - ratio * (cursor.1 - self.pan.1),
+        );
+        self.zoom = Zoom::Scale(new);
+    }
+
+    pub fn pan_by(&mut self, delta: (f32, f32)) {
+        self.pan = (self.pan.0 + delta.0, self.pan.1 + delta.1);
+    }
+
+    /// The affine transform from the unit image quad to clip space, as a column major `mat3`
+    pub fn to_mat(
+        &self,
+        orientation: &Orientation,
+        d_size: (u32, u32),
+        i_size: (u32, u32),
+    ) -> [[f32; 3]; 3] {
+        let scale = self.scale(orientation, d_size, i_size);
+        let to_clip = (2.0 / d_size.0 as f32, 2.0 / d_size.1 as f32);
+        let half = (i_size.0 as f32 / 2.0, i_size.1 as f32 / 2.0);
+        let o = orientation.matrix();
+
+        [
+            [
+                to_clip.0 * scale * o[0][0] * half.0,
+                to_clip.1 * scale * o[0][1] * half.0,
+                0.0,
+            ],
+            [
+                to_clip.0 * scale * o[1][0] * half.1,
+                to_clip.1 * scale * o[1][1] * half.1,
+                0.0,
+            ],
+            [to_clip.0 * self.pan.0, to_clip.1 * self.pan.1, 1.0],
+        ]
+    }
+}
+
+/// The scale at which the oriented image just fits inside the window
+pub fn fit_scale(orientation: &Orientation, d_size: (u32, u32), i_size: (u32, u32)) -> f32 {
+    let (width, height) = orientation.rotated_size(i_size);
+    f32::min(
+        d_size.0 as f32 / width as f32,
+        d_size.1 as f32 / height as f32,
+    )
+}
+
+/// Converts a window position (from the top left, y down) into view space
+pub fn window_to_view(position: (u32, u32), d_size: (u32, u32)) -> (f32, f32) {
+    (
+        position.0 as f32 - d_size.0 as f32 / 2.0,
+        d_size.1 as f32 / 2.0 - position.1 as f32,
+    )
+}
+
+#[cfg(test)]
+mod view_tests {
+    use super::*;
+    use crate::rotation::Rotation;
+
+    fn apply(m: [[f32; 3]; 3], p: (f32, f32)) -> (f32, f32) {
+        (
+            m[0][0] * p.0 + m[1][0] * p.1 + m[2][0],
+            m[0][1] * p.0 + m[1][1] * p.1 + m[2][1],
+        )
+    }
+
+    fn assert_close(a: (f32, f32), b: (f32, f32)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_fit_letterboxes() {
+        let view = View::default();
+        // a wide image in a square window fills the width
+        let m = view.to_mat(&Rotation::Up.into(), (100, 100), (200, 100));
+        assert_close(apply(m, (1.0, 1.0)), (1.0, -0.5));
+        assert_close(apply(m, (-1.0, -1.0)), (-1.0, 0.5));
+
+        // once rotated it is tall, so fills the height instead
+        let m = view.to_mat(&Rotation::Right.into(), (100, 100), (200, 100));
+        assert_close(apply(m, (1.0, 1.0)), (-0.5, -1.0));
+        assert_close(apply(m, (1.0, -1.0)), (0.5, -1.0));
+    }
+
+    #[test]
+    fn test_fit_orientations() {
+        let view = View::default();
+        let corner = (1.0, 1.0);
+        for (rotation, expected) in [
+            (Rotation::Up, (1.0, -1.0)),
+            (Rotation::Right, (-1.0, -1.0)),
+            (Rotation::Down, (-1.0, 1.0)),
+            (Rotation::Left, (1.0, 1.0)),
+        ] {
+            let m = view.to_mat(&rotation.into(), (100, 100), (50, 50));
+            assert_close(apply(m, corner), expected);
+        }
+    }
+
+    #[test]
+    fn test_actual_pixels() {
+        let mut view = View::default();
+        view.actual_pixels();
+        let m = view.to_mat(&Rotation::Up.into(), (400, 200), (100, 50));
+        // the image is 100 pixels wide in a 400 pixel window
+        assert_close(apply(m, (1.0, 1.0)), (0.25, -0.25));
+    }
+
+    #[test]
+    fn test_zoom_at_keeps_cursor_point_fixed() {
+        let d_size = (400, 300);
+        let i_size = (800, 600);
+        let mut view = View::default();
+        let cursor = (50.0, -20.0);
+
+        let before = view.to_mat(&Rotation::Up.into(), d_size, i_size);
+        // the quad position under the cursor before zooming
+        let scale = view.scale(&Rotation::Up.into(), d_size, i_size);
+        let quad = (cursor.0 / (scale * 400.0), -cursor.1 / (scale * 300.0));
+        let clip = (cursor.0 / 200.0, cursor.1 / 150.0);
+        assert_close(apply(before, quad), clip);
+
+        view.zoom_at(ZOOM_STEP, cursor, &Rotation::Up.into(), d_size, i_size);
+        view.zoom_at(ZOOM_STEP, cursor, &Rotation::Up.into(), d_size, i_size);
+        assert_eq!(view.zoom, Zoom::Scale(0.5 * ZOOM_STEP * ZOOM_STEP));
+        let after = view.to_mat(&Rotation::Up.into(), d_size, i_size);
+        assert_close(apply(after, quad), clip);
+    }
+
+    #[test]
+    fn test_pan_translates() {
+        let mut view = View::default();
+        view.pan_by((20.0, -10.0));
+        let m = view.to_mat(&Rotation::Up.into(), (200, 100), (200, 100));
+        assert_close(apply(m, (0.0, 0.0)), (0.2, -0.2));
+
+        view.fit();
+        assert_eq!(view, View::default());
+    }
+
+    #[test]
+    fn test_window_to_view() {
+        assert_eq!(window_to_view((0, 0), (200, 100)), (-100.0, 50.0));
+        assert_eq!(window_to_view((100, 50), (200, 100)), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+/// How often a file that was created is checked until it has stopped growing
+pub const SETTLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change to a file in a watched directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    /// Created, and perhaps still being written
+    Created(PathBuf),
+    /// Renamed into place, so already whole
+    Added(PathBuf),
+    /// Deleted, or renamed away
+    Removed(PathBuf),
+    /// Closed after being written, so it can be read whole
+    Written(PathBuf),
+}
+
+/// The changes a filesystem event describes, a rename being both a removal and an addition
+fn changes(event: notify::Event) -> Vec<FileChange> {
+    let mut paths = event.paths.into_iter();
+    match event.kind {
+        EventKind::Create(_) => paths.map(FileChange::Created).collect(),
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            paths.map(FileChange::Added).collect()
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.map(FileChange::Removed).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            match (paths.next(), paths.next()) {
+                (Some(from), Some(to)) => vec![FileChange::Removed(from), FileChange::Added(to)],
+                _ => Vec::new(),
+            }
+        }
+        // some platforms cannot tell which end of a rename a path is
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .map(|path| {
+                if path.exists() {
+                    FileChange::Added(path)
+                } else {
+                    FileChange::Removed(path)
+                }
+            })
+            .collect(),
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            paths.map(FileChange::Written).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Calls `on_change` from a background thread for each change in `dir`
+///
+/// Watching stops when the returned watcher is dropped.
+pub fn watch<F>(dir: &Path, recursive: bool, on_change: F) -> notify::Result<RecommendedWatcher>
+where
+    F: Fn(FileChange) + Send + 'static,
+{
+    let mut watcher = notify::recommended_watcher(move |event| match event {
+        Ok(event) => changes(event).into_iter().for_each(&on_change),
+        Err(err) => warn!("watch: {}", err),
+    })?;
+    let mode = if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher.watch(dir, mode)?;
+    debug!("watching {:?}", dir);
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use notify::event::{CreateKind, RemoveKind};
+    use std::{fs, sync::mpsc, time::Duration};
+
+    #[test]
+    fn test_changes() {
+        let event = |kind, paths: &[&str]| {
+            changes(notify::Event {
+                kind,
+                paths: paths.iter().map(PathBuf::from).collect(),
+                attrs: Default::default(),
+            })
+        };
+        assert_eq!(
+            event(EventKind::Create(CreateKind::File), &["a.jpg"]),
+            vec![FileChange::Created("a.jpg".into())]
+        );
+        assert_eq!(
+            event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+                &["a.jpg"]
+            ),
+            vec![FileChange::Added("a.jpg".into())]
+        );
+        assert_eq!(
+            event(EventKind::Remove(RemoveKind::Any), &["a.jpg"]),
+            vec![FileChange::Removed("a.jpg".into())]
+        );
+        assert_eq!(
+            event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["a.tmp", "a.jpg"]
+            ),
+            vec![
+                FileChange::Removed("a.tmp".into()),
+                FileChange::Added("a.jpg".into())
+            ]
+        );
+        assert_eq!(
+            event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Any)),
+                &["./test_images/0.jpg", "./test_images/gone.jpg"]
+            ),
+            vec![
+                FileChange::Added("./test_images/0.jpg".into()),
+                FileChange::Removed("./test_images/gone.jpg".into())
+            ]
+        );
+        assert!(event(EventKind::Access(AccessKind::Read), &["a.jpg"]).is_empty());
+    }
+
+    #[test]
+    fn test_watch_directory() {
+        let dir = TestDir::new("watch");
+        let (sender, receiver) = mpsc::channel();
+        let _watcher = watch(&dir, false, move |change| {
+            let _ = sender.send(change);
+        })
+        .unwrap();
+
+        let path = dir.join("a.png");
+        fs::copy("./test_images/dice.png", &path).unwrap();
+        let next = || receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(next(), FileChange::Created(path.clone()));
+        fs::remove_file(&path).unwrap();
+        while next() != FileChange::Removed(path.clone()) {}
+    }
+}